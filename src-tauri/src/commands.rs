@@ -1,31 +1,16 @@
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
 use futures::future::join_all;
 use reqwest::Client;
 use tauri::api::dialog::blocking::FileDialogBuilder;
 use tauri::Manager;
 use tokio::fs;
 use tokio::fs::create_dir;
-use crate::aggregator::aggregate;
-use crate::analyzer::analyze_all;
-
-use crate::grouper::{group_by_na_columns, group_by_peptides};
-use crate::parser::{EngineType, InputFile, parse};
-use crate::processor::process_file;
-use crate::serializer::{serialize, serialize_calculations};
-use tokio::task::{JoinHandle, JoinSet};
-
-pub enum ProgressUpdate {
-    Set {
-        iterations: usize,
-        total_iterations: Option<usize>,
-    },
-    Increment {
-        iterations: usize,
-    },
-}
-
-pub type ProgressCallback = Box<dyn Fn(ProgressUpdate) + Send + Sync>;
+use srm_core::{builtin_engine, ComparisonMethod, DerivedMetric, EngineType, explain_grouping, GroupingPreview, GroupingStrategy, InputFile, InputProblem, OutputFormat, PipelineError, process_file, ProcessOptions, ProgressCallback, ProgressUpdate, ResultOrder, Runner, validate, WorkerPool};
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
 
 #[derive(Clone, serde::Serialize)]
 struct ProgressSetPayload {
@@ -43,37 +28,124 @@ struct ProgressIncrementPayload {
 #[derive(Clone, serde::Serialize)]
 struct ErrorPayload {
     uuid: String,
-    message: String,
+    errors: Vec<FailurePayload>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct FailurePayload {
+    error: PipelineError,
+    peptides: Vec<String>,
 }
 
+#[derive(Clone, serde::Serialize)]
+struct WarningPayload {
+    uuid: String,
+    warnings: Vec<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct CompletePayload {
+    uuid: String,
+    output: String,
+    calculations: usize,
+    failed_datasets: usize,
+    failed_peptides: usize,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct CancelledPayload {
+    uuid: String,
+}
+
+/// Worker pool shared by every `process_data` call, replaced when the workers setting changes.
+/// Files that are already running keep the pool they started with.
+#[derive(Default)]
+pub struct SharedPool(Mutex<WorkerPool>);
+
+/// Per-file tasks started by `process_data`, keyed by input file UUID.
+#[derive(Default)]
+pub struct RunningTasks(Mutex<HashMap<String, AbortHandle>>);
+
 #[tauri::command]
 pub async fn process_data(
     app: tauri::AppHandle,
+    pool: tauri::State<'_, SharedPool>,
+    running_tasks: tauri::State<'_, RunningTasks>,
     engine_type: EngineType,
     should_remove_na_calculations: bool,
     tolerance_multiplier: f64,
+    grouping_strategy: Option<GroupingStrategy>,
     input_files: Vec<InputFile>,
-) -> Result<(), String> {
+    runner: Option<Runner>,
+    engine_timeout: Option<f64>,
+    workers: Option<usize>,
+    output_format: Option<OutputFormat>,
+    split_by: Option<String>,
+    comparison: Option<ComparisonMethod>,
+    derived_metrics: Option<Vec<DerivedMetric>>,
+    result_order: Option<ResultOrder>,
+) -> Result<(), PipelineError> {
     // TODO: https://tauri.app/v1/guides/features/events/
     dbg!("Run");
     dbg!(&input_files);
 
-    let deps_path = match engine_type {
-        EngineType::Single =>
-            app.path_resolver()
-                .resolve_resource("assets").unwrap().join("single-timepoint-engine"),
-        EngineType::Multi => app.path_resolver()
-            .resolve_resource("assets").unwrap().join("multi-timepoint-engine"),
+    let assets_path = app.path_resolver()
+        .resolve_resource("assets").unwrap();
+    let engine = builtin_engine(engine_type, &assets_path, runner.unwrap_or_else(Runner::for_platform));
+    engine.check().map_err(|err| PipelineError::from_engine(err, engine.name()))?;
+
+    let engine_timeout = engine_timeout
+        .map(|seconds| {
+            Some(seconds)
+                .filter(|seconds| *seconds > 0.0)
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .ok_or_else(|| PipelineError::InvalidOption {
+                    name: "engine timeout".to_string(),
+                    message: format!("The engine timeout must be a positive number of seconds, not {seconds}"),
+                })
+        })
+        .transpose()?;
+
+    let options = ProcessOptions {
+        should_remove_na_calculations,
+        tolerance_multiplier,
+        grouping_strategy: check_grouping_strategy(grouping_strategy)?,
+        engine_timeout,
+        output_format: output_format.unwrap_or_default(),
+        condition_row: split_by,
+        comparison,
+        derived_metrics: derived_metrics.unwrap_or_default(),
+        result_order: result_order.unwrap_or_default(),
     };
 
+    let pool = {
+        let wanted = match workers {
+            Some(0) => return Err(PipelineError::InvalidOption {
+                name: "workers".to_string(),
+                message: "The number of workers must be at least 1".to_string(),
+            }),
+            Some(workers) => WorkerPool::new(workers),
+            None => WorkerPool::default(),
+        };
+
+        let mut pool = pool.0.lock().unwrap();
+        if pool.workers() != wanted.workers() {
+            *pool = wanted;
+        }
+        pool.clone()
+    };
     let window = app.get_window("main").unwrap();
-    let mut tasks: Vec<JoinHandle<anyhow::Result<()>>> = vec![];
+    let mut watchers: Vec<JoinHandle<()>> = vec![];
 
     for input_file in input_files {
-        dbg!("hellno");
-        let deps_path = deps_path.clone();
+        let engine = engine.clone();
+        let pool = pool.clone();
+        let options = options.clone();
         let window = window.clone();
         let input_uuid = input_file.uuid.clone();
+        let task_uuid = input_file.uuid.clone();
+        let app = app.clone();
+        let watcher_window = window.clone();
 
         let task = tokio::spawn(async move {
             let window2 = window.clone();
@@ -102,18 +174,53 @@ pub async fn process_data(
             let input_uuid = input_file.uuid.clone();
 
             match process_file(
-                &deps_path,
-                should_remove_na_calculations,
-                tolerance_multiplier,
+                engine.as_ref(),
+                &pool,
+                &options,
                 input_file,
                 progress_callback,
             ).await {
-                Ok(()) => Ok(()),
+                Ok(summary) => {
+                    if !summary.warnings.is_empty() {
+                        let payload = WarningPayload {
+                            uuid: input_uuid.clone(),
+                            warnings: summary.warnings.iter().map(|warning| warning.to_string()).collect(),
+                        };
+                        window.emit("process-warning", payload).unwrap();
+                    }
+
+                    if !summary.failures.is_empty() {
+                        let payload = ErrorPayload {
+                            uuid: input_uuid.clone(),
+                            errors: summary.failures.iter()
+                                .map(|failure| FailurePayload {
+                                    error: failure.error.clone(),
+                                    peptides: failure.peptides.iter().map(|(_, peptide)| peptide.clone()).collect(),
+                                })
+                                .collect(),
+                        };
+                        window.emit("process-error", payload).unwrap();
+                    }
+
+                    let payload = CompletePayload {
+                        uuid: input_uuid,
+                        output: summary.output.to_string_lossy().into_owned(),
+                        calculations: summary.calculations,
+                        failed_datasets: summary.failures.len(),
+                        failed_peptides: summary.failed_peptides(),
+                    };
+                    window.emit("process-complete", payload).unwrap();
+
+                    Ok(())
+                }
                 Err(err) => {
                     dbg!(&err);
                     let payload = ErrorPayload {
                         uuid: input_uuid,
-                        message: err.to_string(),
+                        errors: vec![FailurePayload {
+                            error: err.clone(),
+                            peptides: vec![],
+                        }],
                     };
                     window.emit("process-error", payload).unwrap();
                     Err(err)
@@ -121,12 +228,71 @@ pub async fn process_data(
             }
         });
 
-        dbg!("hello yes");
+        running_tasks.0.lock().unwrap().insert(task_uuid.clone(), task.abort_handle());
 
-        tasks.push(task);
+        // Reports each file as soon as its own task stops, not when the whole batch is done
+        watchers.push(tokio::spawn(async move {
+            let result = task.await;
+            app.state::<RunningTasks>().0.lock().unwrap().remove(&task_uuid);
+
+            if matches!(&result, Err(err) if err.is_cancelled()) {
+                watcher_window.emit("process-cancelled", CancelledPayload { uuid: task_uuid }).unwrap();
+            }
+        }));
     }
 
-    join_all(tasks).await;
+    join_all(watchers).await;
+
+    Ok(())
+}
+
+/// Stops the given input files. Dropping a task kills its engine processes and removes its
+/// temporary data directory; `process-cancelled` is emitted once each task has stopped.
+#[tauri::command]
+pub fn cancel_processing(
+    running_tasks: tauri::State<'_, RunningTasks>,
+    uuids: Vec<String>,
+) -> Result<(), String> {
+    let running_tasks = running_tasks.0.lock().unwrap();
+
+    for uuid in uuids {
+        if let Some(task) = running_tasks.get(&uuid) {
+            task.abort();
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Checks an input file without processing it and returns every problem found, so the
+/// spreadsheet can be fixed in one pass.
+#[tauri::command]
+pub async fn validate_input(input_file: InputFile) -> Result<Vec<InputProblem>, PipelineError> {
+    validate(&input_file.path, input_file.sheet.as_ref()).await
+}
+
+/// Groups the peptides of an input file without running the engine, so the effect of the
+/// tolerance multiplier can be shown before processing.
+#[tauri::command]
+pub async fn preview_grouping(
+    input_file: InputFile,
+    grouping_strategy: Option<GroupingStrategy>,
+    tolerance_multiplier: f64,
+) -> Result<GroupingPreview, PipelineError> {
+    explain_grouping(
+        &input_file.path,
+        input_file.sheet.as_ref(),
+        check_grouping_strategy(grouping_strategy)?,
+        tolerance_multiplier,
+    ).await
+}
+
+fn check_grouping_strategy(strategy: Option<GroupingStrategy>) -> Result<GroupingStrategy, PipelineError> {
+    match strategy.unwrap_or_default() {
+        GroupingStrategy::Ppm { tolerance } if !(tolerance > 0.0 && tolerance.is_finite()) => Err(PipelineError::InvalidOption {
+            name: "ppm tolerance".to_string(),
+            message: format!("The ppm tolerance must be a positive number, not {tolerance}"),
+        }),
+        strategy => Ok(strategy),
+    }
+}
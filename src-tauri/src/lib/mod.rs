@@ -1 +0,0 @@
-pub mod serde;
\ No newline at end of file
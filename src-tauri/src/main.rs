@@ -2,18 +2,18 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod commands;
-mod parser;
-mod grouper;
-mod serializer;
-mod analyzer;
-mod aggregator;
-mod processor;
-mod lib;
 
 #[tokio::main]
 async fn main() {
   tauri::Builder::default()
-    .invoke_handler(tauri::generate_handler![commands::process_data])
+    .manage(commands::SharedPool::default())
+    .manage(commands::RunningTasks::default())
+    .invoke_handler(tauri::generate_handler![
+      commands::process_data,
+      commands::cancel_processing,
+      commands::validate_input,
+      commands::preview_grouping,
+    ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }
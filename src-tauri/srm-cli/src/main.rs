@@ -0,0 +1,540 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use clap::{ArgAction, Parser};
+use futures::future::join_all;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use srm_core::aggregator::read_results;
+use srm_core::grouper::SplitRule;
+use srm_core::serializer::serialize_comparisons;
+use srm_core::{builtin_engine, compare, ComparisonMethod, DerivedMetric, DynEngine, EngineType, ExecutableEngine, explain_grouping, GroupingStrategy, InputFile, OutputFormat, process_file, ProcessOptions, ProgressCallback, ProgressUpdate, ResultOrder, Runner, Severity, Sheet, validate, WorkerPool};
+
+/// Computes rate constants for SRM spreadsheets without the GUI
+#[derive(Parser, Debug)]
+#[command(name = "srm-cli", version)]
+struct Args {
+    /// Input spreadsheets (CSV, Excel or OpenDocument), each one produces a `{name}.RateConst.csv`
+    /// (or `.xlsx`) next to it
+    #[arg(required = true)]
+    input_files: Vec<PathBuf>,
+
+    /// Sheet to read from workbook inputs, by name or by position starting at 0 (defaults to the first one)
+    #[arg(long, value_parser = parse_sheet)]
+    sheet: Option<Sheet>,
+
+    /// Engine used to fit the rate constants: single, multi or native
+    #[arg(long, default_value = "single", value_parser = parse_engine_type)]
+    engine: EngineType,
+
+    /// Drop the samples that are NA for a group instead of passing them to the engine
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    should_remove_na_calculations: bool,
+
+    /// Multiplier applied to the m/z deviation when grouping peptides
+    #[arg(long, default_value_t = 2.0)]
+    tolerance_multiplier: f64,
+
+    /// How the transitions of a peptide are split into charge states: heuristic (m/z gaps against
+    /// `--tolerance-multiplier`), charge (the Charge column of the input) or ppm (isotope spacing
+    /// within `--ppm-tolerance`)
+    #[arg(long, default_value = "heuristic", value_parser = parse_grouping_strategy)]
+    grouping: GroupingStrategy,
+
+    /// Tolerance of `--grouping ppm`, in parts per million of the m/z
+    #[arg(long, default_value_t = 10.0, value_parser = parse_ppm_tolerance)]
+    ppm_tolerance: f64,
+
+    /// Directory containing the engine folders, defaults to `assets` next to this binary
+    #[arg(long)]
+    assets_dir: Option<PathBuf>,
+
+    /// Run this program instead of a bundled engine
+    #[arg(long)]
+    engine_program: Option<PathBuf>,
+
+    /// Argument passed to `--engine-program`, may contain {heavy_water}, {spreadsheet}, {work_dir} and {stem}
+    #[arg(long = "engine-arg", requires = "engine_program", allow_hyphen_values = true)]
+    engine_args: Vec<String>,
+
+    /// Results file written by `--engine-program`, relative to the work directory
+    #[arg(long, requires = "engine_program", default_value = "{stem}.RateConst.csv")]
+    engine_output: String,
+
+    /// Launch the engine through this program, e.g. wine (the default outside Windows)
+    #[arg(long, conflicts_with = "no_wrapper")]
+    wrapper: Option<String>,
+
+    /// Argument passed to `--wrapper` before the engine executable
+    #[arg(long = "wrapper-arg", requires = "wrapper", allow_hyphen_values = true)]
+    wrapper_args: Vec<String>,
+
+    /// Launch the engine directly, even outside Windows
+    #[arg(long)]
+    no_wrapper: bool,
+
+    /// Seconds an engine run may take before its dataset is marked as failed
+    #[arg(long, value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Format of the results: csv, or xlsx for a workbook that also lists the run parameters and omitted samples
+    #[arg(long, default_value = "csv", value_parser = parse_output_format)]
+    output_format: OutputFormat,
+
+    /// Header row whose values, e.g. control and treated, split the samples into separately
+    /// fitted groups. Samples with no value in that row are shared by every group
+    #[arg(long, value_name = "ROW")]
+    split_by: Option<String>,
+
+    /// Compare the rate constants of the conditions with a welch t-test or a bootstrap test,
+    /// written to `{name}.Comparison.csv` (or a sheet of the workbook)
+    #[arg(long, value_parser = parse_comparison_method)]
+    compare: Option<ComparisonMethod>,
+
+    /// Columns computed from the rate constants, with their bounds from the Two_SD columns:
+    /// half-life (days) and turnover (percent per day), comma separated
+    #[arg(long, value_delimiter = ',', value_parser = parse_derived_metric)]
+    derived: Vec<DerivedMetric>,
+
+    /// Order of the result rows: input (the order of the input file) or name (by protein,
+    /// peptide and charge)
+    #[arg(long, default_value = "input", value_parser = parse_result_order)]
+    sort: ResultOrder,
+
+    /// Resamples drawn by `--compare bootstrap`
+    #[arg(long, default_value_t = 10000)]
+    bootstrap_iterations: usize,
+
+    /// Treat the inputs as existing `RateConst.csv` results, one per condition unless they have a
+    /// Condition column, and only write their comparison to this file
+    #[arg(long, value_name = "OUTPUT")]
+    compare_results: Option<PathBuf>,
+
+    /// Maximum number of engine runs at once across all input files, defaults to the CPU count
+    #[arg(long, value_parser = parse_workers)]
+    workers: Option<usize>,
+
+    /// Only check the input files and list every problem found, without processing them
+    #[arg(long)]
+    validate: bool,
+
+    /// Only show how the peptides of the input files are grouped with `--tolerance-multiplier`,
+    /// with the m/z gap and threshold behind every split, without processing them
+    #[arg(long)]
+    preview_grouping: bool,
+
+    /// Environment variable set for the engine, as KEY=VALUE
+    #[arg(long = "env", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+}
+
+fn parse_engine_type(value: &str) -> Result<EngineType, String> {
+    match value {
+        "single" => Ok(EngineType::Single),
+        "multi" => Ok(EngineType::Multi),
+        "native" => Ok(EngineType::Native),
+        _ => Err(format!("unknown engine `{value}`, expected single, multi or native")),
+    }
+}
+
+// The tolerance comes from --ppm-tolerance
+fn parse_grouping_strategy(value: &str) -> Result<GroupingStrategy, String> {
+    match value {
+        "heuristic" => Ok(GroupingStrategy::Heuristic),
+        "charge" => Ok(GroupingStrategy::Charge),
+        "ppm" => Ok(GroupingStrategy::Ppm { tolerance: 10.0 }),
+        _ => Err(format!("unknown grouping `{value}`, expected heuristic, charge or ppm")),
+    }
+}
+
+// The iterations come from --bootstrap-iterations
+fn parse_comparison_method(value: &str) -> Result<ComparisonMethod, String> {
+    match value {
+        "welch" => Ok(ComparisonMethod::Welch),
+        "bootstrap" => Ok(ComparisonMethod::Bootstrap { iterations: 10000 }),
+        _ => Err(format!("unknown comparison `{value}`, expected welch or bootstrap")),
+    }
+}
+
+fn parse_output_format(value: &str) -> Result<OutputFormat, String> {
+    match value {
+        "csv" => Ok(OutputFormat::Csv),
+        "xlsx" => Ok(OutputFormat::Xlsx),
+        _ => Err(format!("unknown output format `{value}`, expected csv or xlsx")),
+    }
+}
+
+fn parse_derived_metric(value: &str) -> Result<DerivedMetric, String> {
+    match value {
+        "half-life" => Ok(DerivedMetric::HalfLife),
+        "turnover" => Ok(DerivedMetric::Turnover),
+        _ => Err(format!("unknown derived column `{value}`, expected half-life or turnover")),
+    }
+}
+
+fn parse_result_order(value: &str) -> Result<ResultOrder, String> {
+    match value {
+        "input" => Ok(ResultOrder::Input),
+        "name" => Ok(ResultOrder::Name),
+        _ => Err(format!("unknown order `{value}`, expected input or name")),
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    value.parse::<f64>().ok()
+        .filter(|seconds| *seconds > 0.0)
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("expected a positive number of seconds, got `{value}`"))
+}
+
+fn parse_workers(value: &str) -> Result<usize, String> {
+    value.parse::<usize>().ok()
+        .filter(|workers| *workers > 0)
+        .ok_or_else(|| format!("expected a positive number of workers, got `{value}`"))
+}
+
+fn parse_ppm_tolerance(value: &str) -> Result<f64, String> {
+    value.parse::<f64>().ok()
+        .filter(|tolerance| *tolerance > 0.0 && tolerance.is_finite())
+        .ok_or_else(|| format!("expected a positive number of ppm, got `{value}`"))
+}
+
+fn parse_sheet(value: &str) -> Result<Sheet, String> {
+    Ok(Sheet::parse(value))
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    value.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{value}`"))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    if args.validate {
+        return validate_files(&args.input_files, args.sheet.as_ref()).await;
+    }
+
+    let grouping_strategy = match args.grouping {
+        GroupingStrategy::Ppm { .. } => GroupingStrategy::Ppm { tolerance: args.ppm_tolerance },
+        strategy => strategy,
+    };
+
+    if args.preview_grouping {
+        return preview_files(&args.input_files, args.sheet.as_ref(), grouping_strategy, args.tolerance_multiplier).await;
+    }
+
+    let comparison = args.compare.map(|method| match method {
+        ComparisonMethod::Bootstrap { .. } => ComparisonMethod::Bootstrap { iterations: args.bootstrap_iterations },
+        method => method,
+    });
+
+    if let Some(output) = &args.compare_results {
+        return compare_results(&args.input_files, output, comparison.unwrap_or(ComparisonMethod::Welch)).await;
+    }
+
+    let assets_dir = match args.assets_dir {
+        Some(assets_dir) => assets_dir,
+        None => std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("assets")))
+            .unwrap_or_else(|| PathBuf::from("assets")),
+    };
+
+    let mut runner = match args.wrapper {
+        Some(wrapper) => Runner::wrapped([vec![wrapper], args.wrapper_args].concat()),
+        None if args.no_wrapper => Runner::direct(),
+        None if args.engine_program.is_some() => Runner::direct(),
+        None => Runner::for_platform(),
+    };
+    for (key, value) in args.env {
+        runner = runner.with_env(key, value);
+    }
+
+    let engine: Arc<dyn DynEngine> = match args.engine_program {
+        Some(program) => Arc::new(
+            ExecutableEngine::new(program, args.engine_args, args.engine_output).with_runner(runner)
+        ),
+        None => builtin_engine(args.engine, &assets_dir, runner),
+    };
+
+    if let Err(err) = engine.check() {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+
+    let pool = match args.workers {
+        Some(workers) => WorkerPool::new(workers),
+        None => WorkerPool::default(),
+    };
+
+    let options = ProcessOptions {
+        should_remove_na_calculations: args.should_remove_na_calculations,
+        tolerance_multiplier: args.tolerance_multiplier,
+        grouping_strategy,
+        engine_timeout: args.timeout,
+        output_format: args.output_format,
+        condition_row: args.split_by,
+        comparison,
+        derived_metrics: args.derived,
+        result_order: args.sort,
+    };
+
+    let bars = MultiProgress::new();
+    let style = ProgressStyle::with_template("{prefix} [{bar:40}] {pos}/{len} {msg}")
+        .unwrap()
+        .progress_chars("=> ");
+
+    let mut tasks = vec![];
+
+    for path in args.input_files {
+        let bar = bars.add(ProgressBar::new(0));
+        bar.set_style(style.clone());
+        bar.set_prefix(path.display().to_string());
+
+        let input_file = InputFile {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            path,
+            sheet: args.sheet.clone(),
+        };
+        let engine = engine.clone();
+        let pool = pool.clone();
+        let options = options.clone();
+
+        tasks.push(tokio::spawn(async move {
+            let progress_bar = bar.clone();
+            let progress_callback: ProgressCallback = Box::new(move |update| {
+                match update {
+                    ProgressUpdate::Set { iterations, total_iterations } => {
+                        if let Some(total_iterations) = total_iterations {
+                            progress_bar.set_length(total_iterations as u64);
+                        }
+                        progress_bar.set_position(iterations as u64);
+                    }
+                    ProgressUpdate::Increment { iterations } => {
+                        progress_bar.inc(iterations as u64);
+                    }
+                }
+            });
+
+            let result = process_file(
+                engine.as_ref(),
+                &pool,
+                &options,
+                input_file,
+                progress_callback,
+            ).await;
+
+            if let Ok(summary) = &result {
+                let prefix = bar.prefix();
+                bar.suspend(|| {
+                    for warning in &summary.warnings {
+                        eprintln!("{prefix}: warning: {warning}");
+                    }
+                });
+            }
+
+            match &result {
+                Ok(summary) if summary.failures.is_empty() => bar.finish_with_message("done"),
+                Ok(summary) => {
+                    let prefix = bar.prefix();
+                    bar.finish_with_message(format!(
+                        "done, {} peptide(s) in {} dataset(s) failed",
+                        summary.failed_peptides(),
+                        summary.failures.len(),
+                    ));
+                    bar.suspend(|| {
+                        for failure in &summary.failures {
+                            let peptides: Vec<&str> = failure.peptides.iter().map(|(_, peptide)| peptide.as_str()).collect();
+                            eprintln!("{prefix}: {} ({})", failure.error, peptides.join(", "));
+                        }
+                    });
+                }
+                Err(err) => {
+                    let prefix = bar.prefix();
+                    bar.abandon_with_message("failed");
+                    // Bars are hidden when stderr isn't a terminal, the error has to show up regardless
+                    bar.suspend(|| eprintln!("{prefix}: {err}"));
+                }
+            }
+
+            result
+        }));
+    }
+
+    let abort_handles: Vec<_> = tasks.iter().map(|task| task.abort_handle()).collect();
+    let results = join_all(tasks);
+    tokio::pin!(results);
+
+    let results = tokio::select! {
+        results = &mut results => results,
+        _ = tokio::signal::ctrl_c() => {
+            for handle in abort_handles {
+                handle.abort();
+            }
+            // Wait for the tasks to drop, which kills the engines and removes the temporary files
+            results.await;
+            bars.clear().ok();
+            eprintln!("Cancelled");
+            return ExitCode::from(130);
+        }
+    };
+
+    let failures = results
+        .into_iter()
+        .filter(|result| !matches!(result, Ok(Ok(_))))
+        .count();
+
+    if failures > 0 {
+        eprintln!("{failures} file{} failed to process", if failures > 1 { "s" } else { "" });
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+async fn compare_results(input_files: &[PathBuf], output: &Path, method: ComparisonMethod) -> ExitCode {
+    let mut calculations = vec![];
+
+    for path in input_files {
+        // Control.RateConst.csv holds the Control condition
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let condition = name.strip_suffix(".RateConst").unwrap_or(&name);
+
+        match read_results(path, Some(condition)).await {
+            Ok(mut results) => calculations.append(&mut results),
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let comparisons = compare(&calculations, method);
+    if let Err(err) = serialize_comparisons(output, &comparisons) {
+        eprintln!("{}: {err}", output.display());
+        return ExitCode::FAILURE;
+    }
+
+    println!("{}: {} comparison(s)", output.display(), comparisons.len());
+    ExitCode::SUCCESS
+}
+
+fn describe_rule(rule: &SplitRule) -> String {
+    match rule {
+        SplitRule::Threshold { threshold: Some(threshold) } => format!("threshold {threshold}"),
+        SplitRule::Threshold { threshold: None } => "no threshold".to_string(),
+        SplitRule::IsotopeSpacing { charge, min, max } => format!("charge {charge} isotope spacing {min} to {max}"),
+        SplitRule::NoIsotopeSpacing => "no isotope spacing".to_string(),
+        SplitRule::ChargeColumn { charge: Some(charge) } => format!("charge column {charge}"),
+        SplitRule::ChargeColumn { charge: None } => "no charge".to_string(),
+    }
+}
+
+async fn preview_files(
+    input_files: &[PathBuf],
+    sheet: Option<&Sheet>,
+    strategy: GroupingStrategy,
+    tolerance_multiplier: f64,
+) -> ExitCode {
+    let mut has_errors = false;
+
+    for path in input_files {
+        let preview = match explain_grouping(path, sheet, strategy, tolerance_multiplier).await {
+            Ok(preview) => preview,
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                has_errors = true;
+                continue;
+            }
+        };
+
+        println!(
+            "{}: {} peptide group(s) in {} dataset(s)",
+            path.display(),
+            preview.peptide_groups.len(),
+            preview.na_groups.len(),
+        );
+
+        for (i, na_group) in preview.na_groups.iter().enumerate() {
+            let missing: Vec<&str> = preview.samples.iter()
+                .zip(&na_group.na_columns)
+                .filter(|(_, &is_na)| is_na)
+                .map(|(sample, _)| sample.as_str())
+                .collect();
+            println!("dataset {}, missing samples: {}", i + 1, if missing.is_empty() { "none".to_string() } else { missing.join(", ") });
+
+            for &group in &na_group.peptide_groups {
+                let group = &preview.peptide_groups[group];
+                println!("  {} {}", group.protein, group.peptide);
+
+                for transition in &group.transitions {
+                    match &transition.decision {
+                        Some(decision) => println!(
+                            "    {} (gap {}, {}, {})",
+                            transition.mass_charge_ratio,
+                            decision.gap,
+                            describe_rule(&decision.rule),
+                            if decision.joined { "joined" } else { "split" },
+                        ),
+                        None => println!("    {}", transition.mass_charge_ratio),
+                    }
+                }
+            }
+        }
+    }
+
+    if has_errors {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+async fn validate_files(input_files: &[PathBuf], sheet: Option<&Sheet>) -> ExitCode {
+    let mut has_errors = false;
+
+    for path in input_files {
+        let problems = match validate(path, sheet).await {
+            Ok(problems) => problems,
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                has_errors = true;
+                continue;
+            }
+        };
+
+        if problems.is_empty() {
+            println!("{}: ok", path.display());
+        }
+
+        for problem in problems {
+            let severity = match problem.severity {
+                Severity::Error => {
+                    has_errors = true;
+                    "error"
+                }
+                Severity::Warning => "warning",
+            };
+            let location = match problem.column {
+                Some(column) => format!("row {}, column {}", problem.row, column),
+                None => format!("row {}", problem.row),
+            };
+            let value = if problem.value.is_empty() {
+                String::new()
+            } else {
+                format!(" (\"{}\")", problem.value)
+            };
+
+            println!("{}: {severity}: {location}: {}{value}", path.display(), problem.message);
+        }
+    }
+
+    if has_errors {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,598 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::anyhow;
+use csv::{ReaderBuilder, StringRecord};
+use serde::Deserialize;
+use tokio::fs;
+use crate::analyzer::{DatasetFailure, DatasetOutcome};
+use crate::parser::{Peptide, Sample};
+use crate::serializer::Dataset;
+
+/// Columns the engine results start with, the names are compared ignoring case.
+pub const ENGINE_HEADERS: [&str; 12] = [
+    "Protein",
+    "Peptide",
+    "NEH",
+    "Charge",
+    "Mean",
+    "nRet",
+    "MPE_0",
+    "MPE_1",
+    "Two_SD_Minus",
+    "nRet",
+    "Two_SD_Plus",
+    "nRet",
+];
+
+/// Numbers the engine results are read as.
+pub trait Numeric: FromStr + Copy {
+    fn is_finite(self) -> bool;
+}
+
+impl Numeric for f64 {
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+}
+
+impl Numeric for u32 {
+    fn is_finite(self) -> bool {
+        true
+    }
+}
+
+/// A number of the engine results along with the text it was read from, so the results can be
+/// written back exactly as the engine wrote them. Values that aren't finite numbers, such as
+/// `-nan(ind)`, have no value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading<T> {
+    pub value: Option<T>,
+    pub text: String,
+}
+
+impl<T: Numeric> Reading<T> {
+    pub fn parse(text: &str) -> Self {
+        Self {
+            value: text.trim().parse::<T>().ok().filter(|value| value.is_finite()),
+            text: text.to_string(),
+        }
+    }
+}
+
+impl<T> fmt::Display for Reading<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[derive(Debug)]
+pub struct Calculation {
+    pub protein: String,
+    pub peptide: String,
+    pub neh: Reading<f64>,
+    pub charge: Reading<u32>,
+    pub mean: Reading<f64>,
+    pub n_ret_1: Reading<u32>,
+    pub mpe_0: Reading<f64>,
+    pub mpe_1: Reading<f64>,
+    pub two_sd_minus: Reading<f64>,
+    pub n_ret_2: Reading<u32>,
+    pub two_sd_plus: Reading<f64>,
+    pub n_ret_3: Reading<u32>,
+    pub samples_omitted: Vec<Sample>,
+    pub condition: Option<String>,
+}
+
+impl Calculation {
+    /// Builds a calculation from the twelve columns of an engine results row.
+    pub fn from_fields(fields: &[&str], samples_omitted: Vec<Sample>, condition: Option<String>) -> Self {
+        let field = |column: usize| fields.get(column).copied().unwrap_or_default();
+
+        Self {
+            protein: field(0).to_string(),
+            peptide: field(1).trim().to_string(),
+            neh: Reading::parse(field(2)),
+            charge: Reading::parse(field(3)),
+            mean: Reading::parse(field(4)),
+            n_ret_1: Reading::parse(field(5)),
+            mpe_0: Reading::parse(field(6)),
+            mpe_1: Reading::parse(field(7)),
+            two_sd_minus: Reading::parse(field(8)),
+            n_ret_2: Reading::parse(field(9)),
+            two_sd_plus: Reading::parse(field(10)),
+            n_ret_3: Reading::parse(field(11)),
+            samples_omitted,
+            condition,
+        }
+    }
+
+    /// Half the distance between the Two_SD columns, which hold the rate constant plus or minus
+    /// two standard errors.
+    pub fn standard_error(&self) -> Option<f64> {
+        match (self.two_sd_minus.value, self.two_sd_plus.value) {
+            (Some(minus), Some(plus)) => Some((plus - minus) / 4.0),
+            _ => None,
+        }
+    }
+
+    /// Days for half of the protein to be replaced, `ln(2) / k`.
+    pub fn half_life(&self) -> Option<f64> {
+        self.mean.value.and_then(half_life)
+    }
+
+    /// Half-lives at the Two_SD bounds of the rate constant, shortest first. A bound at or below
+    /// zero has no half-life.
+    pub fn half_life_bounds(&self) -> (Option<f64>, Option<f64>) {
+        (
+            self.two_sd_plus.value.and_then(half_life),
+            self.two_sd_minus.value.and_then(half_life),
+        )
+    }
+}
+
+fn half_life(rate: f64) -> Option<f64> {
+    (rate > 0.0).then(|| std::f64::consts::LN_2 / rate)
+}
+
+/// Order the rows of the results are written in.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultOrder {
+    /// Order the peptides first appear in the input file
+    #[default]
+    Input,
+    /// By protein, peptide and charge
+    Name,
+}
+
+/// Sorts the calculations, the peptides of each failed dataset and the failed datasets by their
+/// first peptide, so the results come out the same on every run. Rows of the same peptide keep
+/// the order of their conditions.
+/// `peptides` are the peptides of the input file, in input order.
+pub fn sort_results(
+    order: ResultOrder,
+    peptides: &[Peptide],
+    calculations: &mut [Calculation],
+    failures: &mut [DatasetFailure],
+) {
+    let mut rows: HashMap<(&str, &str), usize> = HashMap::new();
+    for (row, peptide) in peptides.iter().enumerate() {
+        rows.entry((peptide.protein.as_str(), peptide.name.as_str())).or_insert(row);
+    }
+    let row = |protein: &str, peptide: &str| rows.get(&(protein, peptide)).copied().unwrap_or(usize::MAX);
+
+    match order {
+        ResultOrder::Input => calculations.sort_by_key(|calculation| {
+            (row(&calculation.protein, &calculation.peptide), calculation.charge.value)
+        }),
+        ResultOrder::Name => calculations.sort_by(|a, b| {
+            (&a.protein, &a.peptide, a.charge.value).cmp(&(&b.protein, &b.peptide, b.charge.value))
+        }),
+    }
+
+    match order {
+        ResultOrder::Input => {
+            for failure in failures.iter_mut() {
+                failure.peptides.sort_by_key(|(protein, peptide)| row(protein, peptide));
+            }
+            failures.sort_by_key(|failure| failure.peptides.first().map(|(protein, peptide)| row(protein, peptide)));
+        }
+        ResultOrder::Name => {
+            for failure in failures.iter_mut() {
+                failure.peptides.sort();
+            }
+            failures.sort_by(|a, b| a.peptides.first().cmp(&b.peptides.first()));
+        }
+    }
+}
+
+pub fn aggregate(outcomes: Vec<DatasetOutcome>) -> (Vec<Calculation>, Vec<DatasetFailure>) {
+    let mut calculations = vec![];
+    let mut failures = vec![];
+
+    for outcome in outcomes {
+        match outcome {
+            Ok(mut dataset_calculations) => calculations.append(&mut dataset_calculations),
+            Err(failure) => failures.push(failure),
+        }
+    }
+
+    (calculations, failures)
+}
+
+pub async fn parse_calculations(spreadsheet: &Path, dataset: &Dataset) -> anyhow::Result<Vec<Calculation>> {
+    let contents = fs::read(spreadsheet).await?;
+    let mut rdr = ReaderBuilder::new()
+        .from_reader(Cursor::new(contents));
+
+    check_engine_headers(rdr.headers()?)?;
+
+    let mut calculations = vec![];
+
+    for result in rdr.records() {
+        let record = result?;
+        if record.len() < ENGINE_HEADERS.len() {
+            return Err(anyhow!(
+                "line {} has {} columns, expected {}",
+                record.position().map_or(0, |position| position.line()),
+                record.len(),
+                ENGINE_HEADERS.len(),
+            ));
+        }
+
+        let fields: Vec<&str> = record.iter().collect();
+        calculations.push(Calculation::from_fields(
+            &fields,
+            dataset.samples_omitted.clone(),
+            dataset.condition.clone(),
+        ));
+    }
+
+    Ok(calculations)
+}
+
+// Catches engines that write their columns in another order before the values end up mislabeled
+fn check_engine_headers(headers: &StringRecord) -> anyhow::Result<()> {
+    for (i, expected) in ENGINE_HEADERS.iter().enumerate() {
+        let found = headers.get(i).unwrap_or_default().trim();
+        if !found.eq_ignore_ascii_case(expected) {
+            return Err(anyhow!(
+                "column {} of the header is \"{found}\", expected \"{expected}\"",
+                i + 1,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the calculations back from a results file written by
+/// [`crate::serializer::serialize_calculations`]. Rows of failed datasets are skipped and the
+/// omitted samples aren't recovered. Rows get their Condition column, or `condition` for results
+/// that weren't split by condition.
+pub async fn read_results(path: &Path, condition: Option<&str>) -> anyhow::Result<Vec<Calculation>> {
+    let contents = fs::read(path).await?;
+    let mut rdr = ReaderBuilder::new()
+        .from_reader(Cursor::new(contents));
+
+    let headers = rdr.headers()?;
+    let has_condition = headers.get(2) == Some("Condition");
+    let notes = headers.len().saturating_sub(1);
+
+    let mut calculations = vec![];
+
+    for result in rdr.records() {
+        let record = result?;
+        let field = |column: usize| record.get(column).unwrap_or_default().to_string();
+        // The notes are the last column, after any derived columns
+        if field(notes).starts_with("Failed") {
+            continue;
+        }
+
+        let row_condition = if has_condition { Some(field(2)).filter(|c| !c.is_empty()) } else { None };
+        let mut fields: Vec<&str> = record.iter().collect();
+        if has_condition && fields.len() > 2 {
+            fields.remove(2);
+        }
+
+        calculations.push(Calculation::from_fields(
+            &fields,
+            vec![],
+            row_condition.or_else(|| condition.map(str::to_string)),
+        ));
+    }
+
+    Ok(calculations)
+}
+
+/// Peptide rate constants of one protein combined. Rate constants that aren't numbers, such as
+/// `-nan(ind)`, and outliers are left out of every statistic.
+#[derive(Debug, Clone)]
+pub struct ProteinSummary {
+    pub protein: String,
+    pub condition: Option<String>,
+    /// Peptide/charge rows contributing to the statistics
+    pub peptides: usize,
+    pub median: f64,
+    /// Weighted by precision (one over the squared standard error taken from the Two_SD columns)
+    /// when every contributing row has one, by nRet otherwise
+    pub weighted_mean: f64,
+    pub first_quartile: f64,
+    pub third_quartile: f64,
+    /// Rows outside 1.5 times the interquartile range, only flagged with four or more rows
+    pub outliers: usize,
+    /// Rows whose rate constant isn't a number
+    pub missing: usize,
+}
+
+impl ProteinSummary {
+    pub fn interquartile_range(&self) -> f64 {
+        self.third_quartile - self.first_quartile
+    }
+}
+
+/// Combines the calculations of each protein, and of each condition for runs split by
+/// condition, in the order the proteins first appear.
+pub fn summarize_proteins(calculations: &[Calculation]) -> Vec<ProteinSummary> {
+    let mut keys: Vec<(&str, Option<&str>)> = vec![];
+    for calculation in calculations {
+        let key = (calculation.protein.as_str(), calculation.condition.as_deref());
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    keys.into_iter()
+        .map(|(protein, condition)| {
+            let rows: Vec<&Calculation> = calculations.iter()
+                .filter(|calculation| calculation.protein == protein && calculation.condition.as_deref() == condition)
+                .collect();
+            summarize_protein(protein, condition, &rows)
+        })
+        .collect()
+}
+
+fn summarize_protein(protein: &str, condition: Option<&str>, rows: &[&Calculation]) -> ProteinSummary {
+    // (rate, precision, nRet)
+    let mut values: Vec<(f64, f64, f64)> = vec![];
+    for calculation in rows {
+        let Some(rate) = calculation.mean.value else {
+            continue;
+        };
+        let precision = 1.0 / calculation.standard_error().unwrap_or(f64::NAN).powi(2);
+        let n_ret = calculation.n_ret_1.value.map_or(0.0, f64::from);
+        values.push((rate, precision, n_ret));
+    }
+    let missing = rows.len() - values.len();
+
+    let mut outliers = 0;
+    if values.len() >= 4 {
+        let rates: Vec<f64> = values.iter().map(|&(rate, _, _)| rate).collect();
+        let (q1, q3) = (quantile(&rates, 0.25), quantile(&rates, 0.75));
+        let (low, high) = (q1 - 1.5 * (q3 - q1), q3 + 1.5 * (q3 - q1));
+        let before = values.len();
+        values.retain(|&(rate, _, _)| rate >= low && rate <= high);
+        outliers = before - values.len();
+    }
+
+    let rates: Vec<f64> = values.iter().map(|&(rate, _, _)| rate).collect();
+    let use_precision = values.iter().all(|&(_, precision, _)| precision.is_finite() && precision > 0.0);
+    let (total, weights) = values.iter()
+        .map(|&(rate, precision, n_ret)| {
+            let weight = if use_precision { precision } else { n_ret };
+            (rate * weight, weight)
+        })
+        .fold((0.0, 0.0), |(total, weights), (value, weight)| (total + value, weights + weight));
+
+    ProteinSummary {
+        protein: protein.to_string(),
+        condition: condition.map(str::to_string),
+        peptides: values.len(),
+        median: quantile(&rates, 0.5),
+        weighted_mean: total / weights,
+        first_quartile: quantile(&rates, 0.25),
+        third_quartile: quantile(&rates, 0.75),
+        outliers,
+        missing,
+    }
+}
+
+// Linear interpolation between the closest ranks, NaN for no values
+fn quantile(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let position = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::PipelineError;
+    use super::*;
+
+    fn peptide(protein: &str, name: &str) -> Peptide {
+        Peptide {
+            name: name.to_string(),
+            protein: protein.to_string(),
+            mass_charge_ratio: 500.0,
+            charge: None,
+            intensities: vec![],
+        }
+    }
+
+    fn failure(peptides: &[(&str, &str)]) -> DatasetFailure {
+        DatasetFailure {
+            peptides: peptides.iter().map(|(protein, name)| (protein.to_string(), name.to_string())).collect(),
+            condition: None,
+            error: PipelineError::Grouping { path: "input.csv".into(), message: "failed".to_string() },
+        }
+    }
+
+    fn names(failures: &[DatasetFailure]) -> Vec<Vec<&str>> {
+        failures.iter()
+            .map(|failure| failure.peptides.iter().map(|(_, name)| name.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_failed_peptides_in_result_order() {
+        let peptides = [
+            peptide("Bsa", "LVTDLTK"),
+            peptide("Bsa", "YLYEIAR"),
+            peptide("Atp5d", "AQSELSGAADEAAR"),
+            peptide("Atp5b", "EGNDLYHEMIESGVINLK"),
+        ];
+        // Grouped datasets list their peptides by name
+        let failures = vec![
+            failure(&[("Atp5b", "EGNDLYHEMIESGVINLK"), ("Bsa", "YLYEIAR")]),
+            failure(&[("Atp5d", "AQSELSGAADEAAR"), ("Bsa", "LVTDLTK")]),
+        ];
+
+        let mut by_input = failures.clone();
+        sort_results(ResultOrder::Input, &peptides, &mut [], &mut by_input);
+        assert_eq!(names(&by_input), [
+            vec!["LVTDLTK", "AQSELSGAADEAAR"],
+            vec!["YLYEIAR", "EGNDLYHEMIESGVINLK"],
+        ]);
+
+        let mut by_name = failures;
+        sort_results(ResultOrder::Name, &peptides, &mut [], &mut by_name);
+        assert_eq!(names(&by_name), [
+            vec!["EGNDLYHEMIESGVINLK", "YLYEIAR"],
+            vec!["AQSELSGAADEAAR", "LVTDLTK"],
+        ]);
+    }
+
+    // A row of the engine results with the given rate constant, nRet and Two_SD columns
+    fn calculation(protein: &str, mean: &str, n_ret: &str, two_sd: (&str, &str)) -> Calculation {
+        let fields = [protein, "LVTDLTK", "4.59", "2", mean, n_ret, "0.5", "0.6", two_sd.0, n_ret, two_sd.1, n_ret];
+        Calculation::from_fields(&fields, vec![], None)
+    }
+
+    fn rates(rates: &[&str]) -> Vec<Calculation> {
+        rates.iter().map(|rate| calculation("Bsa", rate, "8", ("", ""))).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} isn't {expected}");
+    }
+
+    #[test]
+    fn quantiles_interpolate_between_ranks() {
+        let values = [4.0, 1.0, 3.0, 2.0];
+        assert_close(quantile(&values, 0.25), 1.75);
+        assert_close(quantile(&values, 0.5), 2.5);
+        assert_close(quantile(&values, 0.75), 3.25);
+        assert_close(quantile(&[7.0], 0.25), 7.0);
+        assert!(quantile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn summarizes_the_median_and_interquartile_range() {
+        let summaries = summarize_proteins(&rates(&["0.1", "0.4", "0.2", "0.3"]));
+        assert_eq!(summaries.len(), 1);
+
+        let summary = &summaries[0];
+        assert_eq!((summary.protein.as_str(), summary.peptides, summary.outliers, summary.missing), ("Bsa", 4, 0, 0));
+        assert_close(summary.median, 0.25);
+        assert_close(summary.first_quartile, 0.175);
+        assert_close(summary.third_quartile, 0.325);
+        assert_close(summary.interquartile_range(), 0.15);
+    }
+
+    #[test]
+    fn summarizes_each_protein_in_order_of_appearance() {
+        let calculations = [
+            calculation("Bsa", "0.1", "8", ("", "")),
+            calculation("Atp5d", "0.3", "8", ("", "")),
+            calculation("Bsa", "0.2", "8", ("", "")),
+        ];
+        let summaries = summarize_proteins(&calculations);
+
+        let proteins: Vec<(&str, usize)> = summaries.iter().map(|summary| (summary.protein.as_str(), summary.peptides)).collect();
+        assert_eq!(proteins, [("Bsa", 2), ("Atp5d", 1)]);
+    }
+
+    #[test]
+    fn weighs_the_mean_by_precision() {
+        // Standard errors of 0.01 and 0.02, so the first rate weighs four times as much
+        let calculations = [
+            calculation("Bsa", "0.1", "2", ("0.08", "0.12")),
+            calculation("Bsa", "0.2", "8", ("0.16", "0.24")),
+        ];
+        assert_close(summarize_proteins(&calculations)[0].weighted_mean, 0.12);
+    }
+
+    #[test]
+    fn weighs_the_mean_by_n_ret_without_precision() {
+        let calculations = [
+            calculation("Bsa", "0.1", "3", ("0.08", "0.12")),
+            calculation("Bsa", "0.2", "1", ("-nan(ind)", "-nan(ind)")),
+        ];
+        assert_close(summarize_proteins(&calculations)[0].weighted_mean, 0.125);
+    }
+
+    #[test]
+    fn excludes_outliers() {
+        let summary = &summarize_proteins(&rates(&["0.1", "0.11", "0.12", "0.13", "1.0"]))[0];
+        assert_eq!((summary.peptides, summary.outliers), (4, 1));
+        assert_close(summary.median, 0.115);
+        assert_close(summary.weighted_mean, 0.115);
+
+        // Too few rows to tell an outlier apart
+        let summary = &summarize_proteins(&rates(&["0.1", "0.11", "1.0"]))[0];
+        assert_eq!((summary.peptides, summary.outliers), (3, 0));
+    }
+
+    #[test]
+    fn leaves_out_rates_that_arent_numbers() {
+        let summary = &summarize_proteins(&rates(&["0.1", "-nan(ind)", "0.3"]))[0];
+        assert_eq!((summary.peptides, summary.missing), (2, 1));
+        assert_close(summary.median, 0.2);
+        assert_close(summary.weighted_mean, 0.2);
+    }
+
+    #[test]
+    fn summarizes_a_single_peptide() {
+        let summary = &summarize_proteins(&[calculation("Bsa", "0.1", "5", ("", ""))])[0];
+        assert_eq!((summary.peptides, summary.outliers, summary.missing), (1, 0, 0));
+        assert_close(summary.median, 0.1);
+        assert_close(summary.weighted_mean, 0.1);
+        assert_close(summary.interquartile_range(), 0.0);
+    }
+
+    #[test]
+    fn readings_keep_the_engine_text() {
+        let reading = Reading::<f64>::parse("-nan(ind)");
+        assert_eq!((reading.value, reading.to_string()), (None, "-nan(ind)".to_string()));
+
+        let reading = Reading::<f64>::parse(" 0.000940 ");
+        assert_eq!((reading.value, reading.text.as_str()), (Some(0.00094), " 0.000940 "));
+
+        assert_eq!(Reading::<f64>::parse("inf").value, None);
+        assert_eq!(Reading::<u32>::parse("8").value, Some(8));
+        assert_eq!(Reading::<u32>::parse("").value, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_engine_results_with_other_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let dataset = Dataset { samples: vec![], peptides: vec![], samples_omitted: vec![], condition: None };
+
+        let path = dir.path().join("valid.csv");
+        fs::write(&path, "protein,peptide,neh,charge,mean,nret,mpe_0,mpe_1,two_sd_minus,nret,two_sd_plus,nret,\n\
+            Bsa,LVTDLTK,4.59,2,-nan(ind),8,0.51,0.52,-nan(ind),0,-nan(ind),0,\n").await.unwrap();
+        let calculations = parse_calculations(&path, &dataset).await.unwrap();
+        assert_eq!(calculations[0].mean.text, "-nan(ind)");
+
+        let path = dir.path().join("swapped.csv");
+        fs::write(&path, "Protein,Peptide,NEH,Charge,nRet,Mean,MPE_0,MPE_1,Two_SD_Minus,nRet,Two_SD_Plus,nRet\n").await.unwrap();
+        let err = parse_calculations(&path, &dataset).await.unwrap_err();
+        assert_eq!(err.to_string(), "column 5 of the header is \"nRet\", expected \"Mean\"");
+    }
+
+    #[test]
+    fn half_life_bounds_need_a_positive_rate() {
+        let positive = calculation("Bsa", "0.1", "8", ("0.05", "0.2"));
+        assert_close(positive.half_life().unwrap(), std::f64::consts::LN_2 / 0.1);
+        let (lower, upper) = positive.half_life_bounds();
+        assert_close(lower.unwrap(), std::f64::consts::LN_2 / 0.2);
+        assert_close(upper.unwrap(), std::f64::consts::LN_2 / 0.05);
+
+        for two_sd_minus in ["-0.02", "0"] {
+            let (lower, upper) = calculation("Bsa", "0.1", "8", (two_sd_minus, "0.22")).half_life_bounds();
+            assert_close(lower.unwrap(), std::f64::consts::LN_2 / 0.22);
+            assert_eq!(upper, None);
+        }
+        assert_eq!(calculation("Bsa", "-nan(ind)", "8", ("", "")).half_life(), None);
+    }
+}
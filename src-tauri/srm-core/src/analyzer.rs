@@ -0,0 +1,202 @@
+use std::path::Path;
+use std::time::Duration;
+use futures::future::join_all;
+use crate::aggregator::Calculation;
+use crate::engine::DynEngine;
+use crate::error::PipelineError;
+use crate::pool::WorkerPool;
+use crate::progress::{ProgressCallback, ProgressUpdate};
+use crate::serializer::Dataset;
+
+/// A dataset the engine couldn't produce results for.
+#[derive(Debug, Clone)]
+pub struct DatasetFailure {
+    /// Protein and peptide names of the dataset without duplicates, in the order of the grouped
+    /// dataset until [`crate::aggregator::sort_results`] puts them in the result order
+    pub peptides: Vec<(String, String)>,
+    pub condition: Option<String>,
+    pub error: PipelineError,
+}
+
+impl DatasetFailure {
+    pub fn new(dataset: &Dataset, error: PipelineError) -> Self {
+        let mut peptides: Vec<(String, String)> = vec![];
+        for peptide in &dataset.peptides {
+            let key = (peptide.protein.clone(), peptide.name.clone());
+            if !peptides.contains(&key) {
+                peptides.push(key);
+            }
+        }
+
+        Self { peptides, condition: dataset.condition.clone(), error }
+    }
+}
+
+pub type DatasetOutcome = Result<Vec<Calculation>, DatasetFailure>;
+
+/// Runs the engine over every dataset. A dataset that fails or times out is reported as a
+/// [`DatasetFailure`] without affecting the others.
+pub async fn analyze_all(
+    engine: &dyn DynEngine,
+    pool: &WorkerPool,
+    engine_timeout: Option<Duration>,
+    work_dir: &Path,
+    datasets: &[Dataset],
+    progress_callback: &ProgressCallback,
+) -> Vec<DatasetOutcome> {
+    let tasks = datasets.iter().map(|dataset| async move {
+        let result = pool.run(analyze(engine, engine_timeout, work_dir, dataset)).await;
+        // Counts for both writing the inputs and running the engine
+        progress_callback(ProgressUpdate::Increment { iterations: 2 });
+        result
+    });
+
+    // join_all keeps the results in the same order as the datasets
+    join_all(tasks).await
+}
+
+async fn analyze(
+    engine: &dyn DynEngine,
+    engine_timeout: Option<Duration>,
+    work_dir: &Path,
+    dataset: &Dataset,
+) -> DatasetOutcome {
+    let result = match engine_timeout {
+        // A timed out run is dropped, which kills the engine
+        Some(engine_timeout) => match tokio::time::timeout(engine_timeout, engine.analyze(work_dir, dataset)).await {
+            Ok(result) => result.map_err(|err| PipelineError::from_engine(err, engine.name())),
+            Err(_) => Err(PipelineError::EngineTimeout {
+                program: engine.name(),
+                seconds: engine_timeout.as_secs_f64(),
+            }),
+        },
+        None => engine.analyze(work_dir, dataset).await.map_err(|err| PipelineError::from_engine(err, engine.name())),
+    };
+
+    result.map_err(|err| DatasetFailure::new(dataset, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use anyhow::bail;
+    use async_trait::async_trait;
+    use crate::aggregator::{aggregate, ResultOrder, sort_results};
+    use crate::engine::Engine;
+    use crate::parser::Peptide;
+    use crate::serializer::serialize_calculations;
+    use super::*;
+
+    // Fits every dataset except those of the Hung protein, which never finish, and those of the
+    // Broken protein, which fail
+    struct StubEngine;
+
+    #[async_trait]
+    impl Engine for StubEngine {
+        type Job = Vec<(String, String)>;
+
+        fn name(&self) -> String {
+            "stub".to_string()
+        }
+
+        async fn prepare(&self, _work_dir: &Path, dataset: &Dataset) -> anyhow::Result<Self::Job> {
+            Ok(dataset.peptides.iter().map(|peptide| (peptide.protein.clone(), peptide.name.clone())).collect())
+        }
+
+        async fn run(&self, job: &mut Self::Job) -> anyhow::Result<()> {
+            match job[0].0.as_str() {
+                "Hung" => {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(())
+                }
+                "Broken" => bail!("engine crashed"),
+                _ => Ok(()),
+            }
+        }
+
+        async fn collect(&self, job: Self::Job, _dataset: &Dataset) -> anyhow::Result<Vec<Calculation>> {
+            Ok(job.iter()
+                .map(|(protein, peptide)| {
+                    let fields = [protein, peptide, "4.59", "2", "0.05", "8", "0.5", "0.6", "0.04", "6", "0.06", "6"];
+                    Calculation::from_fields(&fields, vec![], None)
+                })
+                .collect())
+        }
+    }
+
+    fn peptide(protein: &str, name: &str) -> Peptide {
+        Peptide {
+            name: name.to_string(),
+            protein: protein.to_string(),
+            mass_charge_ratio: 500.0,
+            charge: None,
+            intensities: vec![],
+        }
+    }
+
+    fn dataset(peptides: &[&Peptide]) -> Dataset {
+        Dataset {
+            samples: vec![],
+            peptides: peptides.iter().map(|&peptide| peptide.clone()).collect(),
+            samples_omitted: vec![],
+            condition: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_results_of_other_datasets_when_one_fails() {
+        let peptides = [
+            peptide("Bsa", "LVTDLTK"),
+            peptide("Broken", "YLYEIAR"),
+            peptide("Bsa", "AEFVEVTK"),
+            peptide("Hung", "LVNELTEFAK"),
+        ];
+        let datasets = [
+            dataset(&[&peptides[3]]),
+            dataset(&[&peptides[0], &peptides[2]]),
+            dataset(&[&peptides[1]]),
+        ];
+
+        let progress = Arc::new(AtomicUsize::new(0));
+        let counter = progress.clone();
+        let progress_callback: ProgressCallback = Box::new(move |update| {
+            if let ProgressUpdate::Increment { iterations } = update {
+                counter.fetch_add(iterations, Ordering::SeqCst);
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = analyze_all(
+            &StubEngine,
+            &WorkerPool::new(2),
+            Some(Duration::from_millis(100)),
+            dir.path(),
+            &datasets,
+            &progress_callback,
+        ).await;
+        assert_eq!(progress.load(Ordering::SeqCst), 6);
+
+        let (mut calculations, mut failures) = aggregate(outcomes);
+        sort_results(ResultOrder::Input, &peptides, &mut calculations, &mut failures);
+        assert!(matches!(failures[0].error, PipelineError::EngineFailure { .. }));
+        assert!(matches!(failures[1].error, PipelineError::EngineTimeout { .. }));
+
+        let path = dir.path().join("results.csv");
+        serialize_calculations(&path, &calculations, &failures, &[]).unwrap();
+        let rows: Vec<(String, String, String)> = csv::Reader::from_path(&path).unwrap()
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (record[1].to_string(), record[4].to_string(), record[record.len() - 1].to_string())
+            })
+            .collect();
+
+        assert_eq!(rows, [
+            ("LVTDLTK".to_string(), "0.05".to_string(), String::new()),
+            ("AEFVEVTK".to_string(), "0.05".to_string(), String::new()),
+            ("YLYEIAR".to_string(), String::new(), "Failed: engine crashed".to_string()),
+            ("LVNELTEFAK".to_string(), String::new(), "Failed: The engine didn't finish within 0.1 seconds".to_string()),
+        ]);
+    }
+}
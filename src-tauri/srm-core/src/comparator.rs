@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::aggregator::Calculation;
+
+// Fixed so a bootstrap comparison gives the same p-values every time it's run
+const BOOTSTRAP_SEED: u64 = 0x5eed_2024;
+
+/// Test used to compare the rate constants of two conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ComparisonMethod {
+    /// Welch's unequal variances t-test.
+    Welch,
+    /// Two-sided bootstrap test of the difference in means under a shared mean.
+    Bootstrap { iterations: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonLevel {
+    /// One rate constant row per condition, compared with the spread reported for the fit.
+    Peptide,
+    /// The rate constants of a protein's peptides, compared as observations of each condition.
+    Protein,
+}
+
+impl ComparisonLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComparisonLevel::Peptide => "Peptide",
+            ComparisonLevel::Protein => "Protein",
+        }
+    }
+}
+
+/// Difference between the rate constants of `condition_a` and `condition_b`.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub level: ComparisonLevel,
+    pub protein: String,
+    /// Empty for protein level comparisons
+    pub peptide: String,
+    pub condition_a: String,
+    pub condition_b: String,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub n_a: usize,
+    pub n_b: usize,
+    /// `mean_b - mean_a`
+    pub difference: f64,
+    /// `mean_b / mean_a`
+    pub fold_change: f64,
+    /// The t statistic, NaN for bootstrap tests
+    pub statistic: f64,
+    pub p_value: f64,
+    /// Benjamini-Hochberg adjusted p-value across the comparisons of the same level
+    pub q_value: f64,
+}
+
+impl Comparison {
+    pub fn log2_fold_change(&self) -> f64 {
+        self.fold_change.log2()
+    }
+}
+
+// Summary of one side of a comparison: the rate constant and its standard error
+#[derive(Debug, Clone)]
+struct Group {
+    mean: f64,
+    standard_error: f64,
+    n: usize,
+    // Individual rate constants, empty for peptide level groups
+    observations: Vec<f64>,
+}
+
+/// Compares every pair of conditions in `calculations`, for each peptide and each protein. The
+/// conditions are paired in the order they first appear, so `condition_a` is the earlier one.
+///
+/// Calculations without a condition, or whose rate constant isn't a number, are left out.
+pub fn compare(calculations: &[Calculation], method: ComparisonMethod) -> Vec<Comparison> {
+    let mut conditions: Vec<&str> = vec![];
+    for condition in calculations.iter().filter_map(|calculation| calculation.condition.as_deref()) {
+        if !conditions.contains(&condition) {
+            conditions.push(condition);
+        }
+    }
+
+    let mut rng = SplitMix64(BOOTSTRAP_SEED);
+    let mut peptides = compare_peptides(calculations, &conditions, method, &mut rng);
+    let mut proteins = compare_proteins(calculations, &conditions, method, &mut rng);
+    adjust_p_values(&mut peptides);
+    adjust_p_values(&mut proteins);
+
+    peptides.append(&mut proteins);
+    peptides
+}
+
+fn compare_peptides(
+    calculations: &[Calculation],
+    conditions: &[&str],
+    method: ComparisonMethod,
+    rng: &mut SplitMix64,
+) -> Vec<Comparison> {
+    // (protein, peptide, charge) in input order, then the row of each condition
+    let mut keys: Vec<(&str, &str, &str)> = vec![];
+    let mut groups: HashMap<((&str, &str, &str), &str), Group> = HashMap::new();
+
+    for calculation in calculations {
+        let (Some(condition), Some(group)) = (calculation.condition.as_deref(), peptide_group(calculation)) else {
+            continue;
+        };
+
+        let key = (calculation.protein.as_str(), calculation.peptide.as_str(), calculation.charge.text.as_str());
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+        groups.insert((key, condition), group);
+    }
+
+    let mut comparisons = vec![];
+    for &key in &keys {
+        for (i, &condition_a) in conditions.iter().enumerate() {
+            for &condition_b in &conditions[i + 1..] {
+                if let (Some(a), Some(b)) = (groups.get(&(key, condition_a)), groups.get(&(key, condition_b))) {
+                    comparisons.push(compare_groups(
+                        ComparisonLevel::Peptide, key.0, key.1, condition_a, condition_b, a, b, method, rng,
+                    ));
+                }
+            }
+        }
+    }
+
+    comparisons
+}
+
+fn compare_proteins(
+    calculations: &[Calculation],
+    conditions: &[&str],
+    method: ComparisonMethod,
+    rng: &mut SplitMix64,
+) -> Vec<Comparison> {
+    let mut proteins: Vec<&str> = vec![];
+    let mut rates: HashMap<(&str, &str), Vec<f64>> = HashMap::new();
+
+    for calculation in calculations {
+        let (Some(condition), Some(rate)) = (calculation.condition.as_deref(), calculation.mean.value) else {
+            continue;
+        };
+
+        if !proteins.contains(&calculation.protein.as_str()) {
+            proteins.push(&calculation.protein);
+        }
+        rates.entry((&calculation.protein, condition)).or_default().push(rate);
+    }
+
+    let mut comparisons = vec![];
+    for &protein in &proteins {
+        for (i, &condition_a) in conditions.iter().enumerate() {
+            for &condition_b in &conditions[i + 1..] {
+                let (Some(a), Some(b)) = (rates.get(&(protein, condition_a)), rates.get(&(protein, condition_b))) else {
+                    continue;
+                };
+
+                comparisons.push(compare_groups(
+                    ComparisonLevel::Protein,
+                    protein,
+                    "",
+                    condition_a,
+                    condition_b,
+                    &observation_group(a),
+                    &observation_group(b),
+                    method,
+                    rng,
+                ));
+            }
+        }
+    }
+
+    comparisons
+}
+
+fn peptide_group(calculation: &Calculation) -> Option<Group> {
+    Some(Group {
+        mean: calculation.mean.value?,
+        standard_error: calculation.standard_error().unwrap_or(f64::NAN),
+        n: calculation.n_ret_2.value.unwrap_or(0) as usize,
+        observations: vec![],
+    })
+}
+
+fn observation_group(observations: &[f64]) -> Group {
+    let n = observations.len();
+    let mean = observations.iter().sum::<f64>() / n as f64;
+    let variance = observations.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n as f64 - 1.0);
+
+    Group {
+        mean,
+        standard_error: (variance / n as f64).sqrt(),
+        n,
+        observations: observations.to_vec(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compare_groups(
+    level: ComparisonLevel,
+    protein: &str,
+    peptide: &str,
+    condition_a: &str,
+    condition_b: &str,
+    a: &Group,
+    b: &Group,
+    method: ComparisonMethod,
+    rng: &mut SplitMix64,
+) -> Comparison {
+    let (statistic, p_value) = match method {
+        ComparisonMethod::Welch => welch_test(a, b),
+        ComparisonMethod::Bootstrap { iterations } => (f64::NAN, bootstrap_test(a, b, iterations, rng)),
+    };
+
+    Comparison {
+        level,
+        protein: protein.to_string(),
+        peptide: peptide.to_string(),
+        condition_a: condition_a.to_string(),
+        condition_b: condition_b.to_string(),
+        mean_a: a.mean,
+        mean_b: b.mean,
+        n_a: a.n,
+        n_b: b.n,
+        difference: b.mean - a.mean,
+        fold_change: b.mean / a.mean,
+        statistic,
+        p_value,
+        q_value: f64::NAN,
+    }
+}
+
+// Returns the t statistic and the two-sided p-value, NaN when a side has fewer than two values
+fn welch_test(a: &Group, b: &Group) -> (f64, f64) {
+    if a.n < 2 || b.n < 2 {
+        return (f64::NAN, f64::NAN);
+    }
+
+    let (var_a, var_b) = (a.standard_error.powi(2), b.standard_error.powi(2));
+    let t = (b.mean - a.mean) / (var_a + var_b).sqrt();
+    let df = (var_a + var_b).powi(2)
+        / (var_a.powi(2) / (a.n as f64 - 1.0) + var_b.powi(2) / (b.n as f64 - 1.0));
+
+    if !t.is_finite() || !df.is_finite() {
+        return (t, f64::NAN);
+    }
+
+    (t, incomplete_beta(df / 2.0, 0.5, df / (df + t * t)))
+}
+
+// Both sides are shifted to the pooled mean so the resampled differences follow the null
+// hypothesis. Peptide rows only carry a mean and a standard error, so they're drawn from a
+// normal distribution instead of resampled.
+fn bootstrap_test(a: &Group, b: &Group, iterations: usize, rng: &mut SplitMix64) -> f64 {
+    if a.n < 2 || b.n < 2 || iterations == 0 {
+        return f64::NAN;
+    }
+
+    let observed = (b.mean - a.mean).abs();
+    if !observed.is_finite() || !(a.standard_error + b.standard_error).is_finite() {
+        return f64::NAN;
+    }
+
+    let pooled = (a.mean * a.n as f64 + b.mean * b.n as f64) / (a.n + b.n) as f64;
+
+    let draw = |group: &Group, rng: &mut SplitMix64| -> f64 {
+        if group.observations.is_empty() {
+            pooled + group.standard_error * rng.next_normal()
+        } else {
+            let total: f64 = (0..group.n)
+                .map(|_| group.observations[rng.next_index(group.n)] - group.mean + pooled)
+                .sum();
+            total / group.n as f64
+        }
+    };
+
+    let mut extreme = 0;
+    for _ in 0..iterations {
+        let difference = draw(b, rng) - draw(a, rng);
+        if difference.abs() >= observed {
+            extreme += 1;
+        }
+    }
+
+    (extreme + 1) as f64 / (iterations + 1) as f64
+}
+
+// Benjamini-Hochberg step-up procedure, comparisons without a p-value are left out
+fn adjust_p_values(comparisons: &mut [Comparison]) {
+    let mut order: Vec<usize> = (0..comparisons.len())
+        .filter(|&i| comparisons[i].p_value.is_finite())
+        .collect();
+    order.sort_by(|&i, &j| comparisons[i].p_value.total_cmp(&comparisons[j].p_value));
+
+    let m = order.len() as f64;
+    let mut running_min: f64 = 1.0;
+    for (rank, &i) in order.iter().enumerate().rev() {
+        let q = comparisons[i].p_value * m / (rank + 1) as f64;
+        running_min = running_min.min(q);
+        comparisons[i].q_value = running_min;
+    }
+}
+
+// Regularized incomplete beta function I_x(a, b), by continued fraction
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+
+        let numerator = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { 1.0 / TINY } else { 1.0 / d };
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        h *= d * c;
+
+        let numerator = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { 1.0 / TINY } else { 1.0 / d };
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+
+    h
+}
+
+// Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series: f64 = COEFFICIENTS.iter().enumerate()
+        .map(|(i, coefficient)| coefficient / (x + 1.0 + i as f64))
+        .sum();
+
+    -tmp + (2.506_628_274_631_000_5 * (1.000_000_000_190_015 + series) / x).ln()
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in (0, 1)
+    fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    fn next_index(&mut self, len: usize) -> usize {
+        (self.next_f64() * len as f64) as usize
+    }
+
+    // Box-Muller transform
+    fn next_normal(&mut self) -> f64 {
+        let (u, v) = (self.next_f64(), self.next_f64());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "expected {expected}, got {actual}");
+    }
+
+    fn summary(mean: f64, standard_error: f64, n: usize) -> Group {
+        Group { mean, standard_error, n, observations: vec![] }
+    }
+
+    fn comparison(p_value: f64) -> Comparison {
+        Comparison {
+            level: ComparisonLevel::Peptide,
+            protein: "Bsa".to_string(),
+            peptide: "LVTDLTK".to_string(),
+            condition_a: "Control".to_string(),
+            condition_b: "Treated".to_string(),
+            mean_a: f64::NAN,
+            mean_b: f64::NAN,
+            n_a: 0,
+            n_b: 0,
+            difference: f64::NAN,
+            fold_change: f64::NAN,
+            statistic: f64::NAN,
+            p_value,
+            q_value: f64::NAN,
+        }
+    }
+
+    // A rate constant row with a standard error of 0.001 from 9 replicates
+    fn calculation(protein: &str, peptide: &str, mean: f64, condition: &str) -> Calculation {
+        let (minus, plus) = ((mean - 0.002).to_string(), (mean + 0.002).to_string());
+        let fields = [protein, peptide, "4.59", "2", &mean.to_string(), "9", "0.4", "0.5", &minus, "9", &plus, "9"];
+        Calculation::from_fields(&fields, vec![], Some(condition.to_string()))
+    }
+
+    #[test]
+    fn ln_gamma_matches_known_values() {
+        assert_close(ln_gamma(1.0), 0.0, 1e-10);
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-10);
+        assert_close(ln_gamma(0.5), 0.572_364_942_924_700_1, 1e-10);
+        assert_close(ln_gamma(10.3), 13.482_036_786_138_359, 1e-9);
+    }
+
+    #[test]
+    fn incomplete_beta_matches_known_values() {
+        assert_eq!(incomplete_beta(2.0, 3.0, 0.0), 0.0);
+        assert_eq!(incomplete_beta(2.0, 3.0, 1.0), 1.0);
+        assert_close(incomplete_beta(1.0, 1.0, 0.3), 0.3, 1e-12);
+        assert_close(incomplete_beta(4.0, 4.0, 0.5), 0.5, 1e-12);
+        assert_close(incomplete_beta(2.0, 3.0, 0.3), 0.3483, 1e-10);
+    }
+
+    #[test]
+    fn welch_test_matches_the_t_distribution() {
+        // Equal standard errors and sizes of 6 give 10 degrees of freedom
+        let difference = 2.0 * 0.02f64.sqrt();
+        let (t, p) = welch_test(&summary(1.0, 0.1, 6), &summary(1.0 + difference, 0.1, 6));
+        assert_close(t, 2.0, 1e-12);
+        assert_close(p, 0.073_388_034_770_740_4, 1e-9);
+
+        let (t, p) = welch_test(&summary(1.0 + difference, 0.1, 6), &summary(1.0, 0.1, 6));
+        assert_close(t, -2.0, 1e-12);
+        assert_close(p, 0.073_388_034_770_740_4, 1e-9);
+
+        // The critical value of a 5% two-sided test at 10 degrees of freedom
+        let difference = 2.228_138_851_986 * 0.02f64.sqrt();
+        let (_, p) = welch_test(&summary(1.0, 0.1, 6), &summary(1.0 + difference, 0.1, 6));
+        assert_close(p, 0.05, 1e-9);
+    }
+
+    #[test]
+    fn welch_test_uses_the_welch_satterthwaite_degrees_of_freedom() {
+        // var_a = 0.01, var_b = 0.04: df = 0.0025 / (0.0001 / 3 + 0.0016 / 8) = 75 / 7
+        let (a, b) = (summary(1.0, 0.1, 4), summary(1.5, 0.2, 9));
+        let (t, p) = welch_test(&a, &b);
+        let df = 75.0 / 7.0;
+
+        assert_close(t, 0.5 / 0.05f64.sqrt(), 1e-12);
+        assert_close(p, incomplete_beta(df / 2.0, 0.5, df / (df + t * t)), 1e-15);
+    }
+
+    #[test]
+    fn welch_test_needs_two_values_a_side() {
+        let (_, p) = welch_test(&summary(1.0, 0.1, 1), &summary(2.0, 0.1, 6));
+        assert!(p.is_nan());
+    }
+
+    #[test]
+    fn benjamini_hochberg_matches_a_worked_example() {
+        let mut comparisons: Vec<Comparison> = [0.01, 0.04, 0.03, 0.005, f64::NAN]
+            .into_iter()
+            .map(comparison)
+            .collect();
+        adjust_p_values(&mut comparisons);
+
+        // Ranked: 0.005 * 4 / 1, 0.01 * 4 / 2, 0.03 * 4 / 3, 0.04 * 4 / 4, then the running minimum
+        let q_values: Vec<f64> = comparisons.iter().map(|comparison| comparison.q_value).collect();
+        for (actual, expected) in q_values.iter().zip([0.02, 0.04, 0.04, 0.02]) {
+            assert_close(*actual, expected, 1e-15);
+        }
+        assert!(q_values[4].is_nan());
+    }
+
+    #[test]
+    fn split_mix_64_matches_the_reference_sequence() {
+        let mut rng = SplitMix64(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn bootstrap_is_reproducible() {
+        let a = observation_group(&[0.010, 0.012, 0.011, 0.013, 0.009]);
+        let b = observation_group(&[0.014, 0.013, 0.016, 0.012, 0.015]);
+
+        let first = bootstrap_test(&a, &b, 2000, &mut SplitMix64(BOOTSTRAP_SEED));
+        let second = bootstrap_test(&a, &b, 2000, &mut SplitMix64(BOOTSTRAP_SEED));
+        assert_eq!(first, second);
+        assert!(first > 0.0 && first < 0.05, "p-value {first}");
+
+        let same = bootstrap_test(&a, &a, 2000, &mut SplitMix64(BOOTSTRAP_SEED));
+        assert!(same > 0.5, "p-value {same}");
+    }
+
+    #[test]
+    fn compare_gives_the_same_bootstrap_results_every_run() {
+        let calculations = vec![
+            calculation("Bsa", "LVTDLTK", 0.010, "Control"),
+            calculation("Bsa", "YLYEIAR", 0.012, "Control"),
+            calculation("Bsa", "LVTDLTK", 0.014, "Treated"),
+            calculation("Bsa", "YLYEIAR", 0.013, "Treated"),
+        ];
+        let method = ComparisonMethod::Bootstrap { iterations: 1000 };
+
+        let p_values = |comparisons: Vec<Comparison>| -> Vec<(String, f64, f64)> {
+            comparisons.into_iter().map(|c| (c.peptide, c.p_value, c.q_value)).collect()
+        };
+        let first = p_values(compare(&calculations, method));
+        assert_eq!(first, p_values(compare(&calculations, method)));
+
+        // Two peptide comparisons and one protein comparison
+        assert_eq!(first.len(), 3);
+        assert!(first.iter().all(|(_, p, _)| *p > 0.0 && *p <= 1.0));
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::fs::remove_file;
+use crate::aggregator::{Calculation, parse_calculations};
+use crate::error::PipelineError;
+use crate::fitter::NativeEngine;
+use crate::parser::EngineType;
+use crate::runner::{ProcessGroup, Runner};
+use crate::serializer::{Dataset, serialize_dataset};
+
+/// Computes the rate constants of one dataset in three steps.
+///
+/// `prepare` turns the dataset into whatever the engine reads (usually files in `work_dir`),
+/// `run` does the fitting and `collect` reads the results back and cleans up after the job.
+///
+/// Errors that are a [`PipelineError`] are reported as is, anything else as an engine failure.
+#[async_trait]
+pub trait Engine: Send + Sync {
+    type Job: Send + Sync;
+
+    /// Describes the engine in the run parameters written next to the results.
+    fn name(&self) -> String;
+
+    /// Reports problems that would make every run fail, such as a missing executable.
+    fn check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn prepare(&self, work_dir: &Path, dataset: &Dataset) -> anyhow::Result<Self::Job>;
+
+    async fn run(&self, job: &mut Self::Job) -> anyhow::Result<()>;
+
+    async fn collect(&self, job: Self::Job, dataset: &Dataset) -> anyhow::Result<Vec<Calculation>>;
+}
+
+/// Object safe form of [`Engine`] so engines can be picked at runtime.
+#[async_trait]
+pub trait DynEngine: Send + Sync {
+    fn name(&self) -> String;
+
+    fn check(&self) -> anyhow::Result<()>;
+
+    async fn analyze(&self, work_dir: &Path, dataset: &Dataset) -> anyhow::Result<Vec<Calculation>>;
+}
+
+#[async_trait]
+impl<E: Engine> DynEngine for E {
+    fn name(&self) -> String {
+        Engine::name(self)
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        Engine::check(self)
+    }
+
+    async fn analyze(&self, work_dir: &Path, dataset: &Dataset) -> anyhow::Result<Vec<Calculation>> {
+        let mut job = self.prepare(work_dir, dataset).await?;
+        self.run(&mut job).await?;
+        self.collect(job, dataset).await
+    }
+}
+
+/// Returns the engine bundled with the app for an engine type.
+pub fn builtin_engine(engine_type: EngineType, assets_dir: &Path, runner: Runner) -> Arc<dyn DynEngine> {
+    match engine_type {
+        EngineType::Single => Arc::new(
+            ExecutableEngine::srm_rate(&assets_dir.join("single-timepoint-engine")).with_runner(runner)
+        ),
+        EngineType::Multi => Arc::new(
+            ExecutableEngine::srm_rate(&assets_dir.join("multi-timepoint-engine")).with_runner(runner)
+        ),
+        EngineType::Native => Arc::new(NativeEngine),
+    }
+}
+
+/// An external program reading the spreadsheet and heavy water files written by the serializer.
+///
+/// `args` and `output` are templates where `{heavy_water}`, `{spreadsheet}` and `{work_dir}`
+/// are replaced by paths and `{stem}` by the spreadsheet's file name without extension.
+/// `output` is resolved relative to the work directory. The program is launched through `runner`.
+#[derive(Debug, Clone)]
+pub struct ExecutableEngine {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub output: String,
+    pub runner: Runner,
+}
+
+pub struct ExecutableJob {
+    pub spreadsheet: PathBuf,
+    pub heavy_water: PathBuf,
+    pub work_dir: PathBuf,
+    pub output: PathBuf,
+}
+
+impl ExecutableEngine {
+    pub fn new(program: PathBuf, args: Vec<String>, output: String) -> Self {
+        Self {
+            program,
+            args,
+            output,
+            runner: Runner::direct(),
+        }
+    }
+
+    pub fn with_runner(mut self, runner: Runner) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    pub fn srm_rate(deps_dir: &Path) -> Self {
+        Self::new(
+            deps_dir.join("SRM_Rate.exe"),
+            vec!["{heavy_water}".to_string(), "{spreadsheet}".to_string()],
+            "{stem}.RateConst.csv".to_string(),
+        ).with_runner(Runner::for_platform())
+    }
+
+    fn render(template: &str, spreadsheet: &Path, heavy_water: &Path, work_dir: &Path) -> String {
+        let stem = spreadsheet.file_stem().unwrap_or_default().to_string_lossy();
+
+        template
+            .replace("{heavy_water}", &heavy_water.to_string_lossy())
+            .replace("{spreadsheet}", &spreadsheet.to_string_lossy())
+            .replace("{work_dir}", &work_dir.to_string_lossy())
+            .replace("{stem}", &stem)
+    }
+}
+
+#[async_trait]
+impl Engine for ExecutableEngine {
+    type Job = ExecutableJob;
+
+    fn name(&self) -> String {
+        self.program.display().to_string()
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        self.runner.check()?;
+
+        if self.program.components().count() > 1 && !self.program.is_file() {
+            return Err(PipelineError::EngineLaunch {
+                program: self.program.display().to_string(),
+                message: format!("The engine executable {} doesn't exist", self.program.display()),
+            }.into());
+        }
+
+        Ok(())
+    }
+
+    async fn prepare(&self, work_dir: &Path, dataset: &Dataset) -> anyhow::Result<ExecutableJob> {
+        let (spreadsheet, heavy_water) = serialize_dataset(work_dir, dataset).await
+            .map_err(|err| PipelineError::Serialization {
+                path: work_dir.to_path_buf(),
+                message: format!("Couldn't write the engine input: {err}"),
+            })?;
+        let output = work_dir.join(Self::render(&self.output, &spreadsheet, &heavy_water, work_dir));
+
+        Ok(ExecutableJob {
+            spreadsheet,
+            heavy_water,
+            work_dir: work_dir.to_path_buf(),
+            output,
+        })
+    }
+
+    async fn run(&self, job: &mut ExecutableJob) -> anyhow::Result<()> {
+        let mut command = self.runner.command(&self.program);
+        // Cancelling a job drops this future, which must not leave the engine running, nor
+        // anything it started
+        command.kill_on_drop(true)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        for arg in &self.args {
+            command.arg(Self::render(arg, &job.spreadsheet, &job.heavy_water, &job.work_dir));
+        }
+
+        let launch_error = |err: std::io::Error| PipelineError::EngineLaunch {
+            program: self.program.display().to_string(),
+            message: format!("Command couldn't run: {err}"),
+        };
+        let (child, _group) = ProcessGroup::spawn(&mut command).map_err(launch_error)?;
+        let output = child.wait_with_output().await.map_err(launch_error)?;
+
+        remove_file(&job.heavy_water)
+            .await
+            .map_err(|err| PipelineError::Serialization {
+                path: job.heavy_water.clone(),
+                message: format!("Couldn't delete heavy water file: {err}"),
+            })?;
+        remove_file(&job.spreadsheet)
+            .await
+            .map_err(|err| PipelineError::Serialization {
+                path: job.spreadsheet.clone(),
+                message: format!("Couldn't delete spreadsheet file: {err}"),
+            })?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(PipelineError::EngineFailure {
+                program: self.program.display().to_string(),
+                status: output.status.code(),
+                message: "The command didn't complete successfully".to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }.into())
+        }
+    }
+
+    async fn collect(&self, job: ExecutableJob, dataset: &Dataset) -> anyhow::Result<Vec<Calculation>> {
+        let calculations = parse_calculations(&job.output, dataset).await
+            .map_err(|err| PipelineError::Aggregation {
+                path: job.output.clone(),
+                message: format!("Couldn't read the engine results: {err}"),
+            })?;
+        remove_file(&job.output).await
+            .map_err(|err| PipelineError::Serialization {
+                path: job.output.clone(),
+                message: format!("Couldn't delete the engine results: {err}"),
+            })?;
+
+        Ok(calculations)
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+use std::path::PathBuf;
+use serde::Serialize;
+
+/// Everything that can stop a file, or one of its datasets, from being processed.
+///
+/// Serialized with a `kind` tag so the GUI can tell the stages apart.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PipelineError {
+    /// The input file couldn't be read or doesn't follow the expected layout.
+    Parse {
+        path: PathBuf,
+        row: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// The peptides couldn't be split into datasets.
+    Grouping {
+        path: PathBuf,
+        message: String,
+    },
+    /// Writing the engine inputs, the results or the temporary files failed.
+    Serialization {
+        path: PathBuf,
+        message: String,
+    },
+    /// The engine, or the program it's launched through, couldn't be started.
+    EngineLaunch {
+        program: String,
+        message: String,
+    },
+    /// The engine ran but didn't produce results.
+    EngineFailure {
+        program: String,
+        status: Option<i32>,
+        message: String,
+        stderr: String,
+    },
+    /// The engine didn't finish within the engine timeout and was killed.
+    EngineTimeout {
+        program: String,
+        seconds: f64,
+    },
+    /// The results written by the engine couldn't be read back.
+    Aggregation {
+        path: PathBuf,
+        message: String,
+    },
+    /// A setting of the run has a value that can't be used.
+    InvalidOption {
+        name: String,
+        message: String,
+    },
+}
+
+impl PipelineError {
+    /// Recovers the pipeline error behind an error returned by an [`crate::Engine`]. Errors that
+    /// aren't pipeline errors, such as those of custom engines, become engine failures.
+    pub fn from_engine(err: anyhow::Error, program: String) -> Self {
+        match err.downcast::<PipelineError>() {
+            Ok(err) => err,
+            Err(err) => PipelineError::EngineFailure {
+                program,
+                status: None,
+                message: err.to_string(),
+                stderr: String::new(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Parse { row, column, message, .. } => match (row, column) {
+                (Some(row), Some(column)) => write!(f, "{message} (row {row}, column {column})"),
+                (Some(row), None) => write!(f, "{message} (row {row})"),
+                _ => write!(f, "{message}"),
+            },
+            PipelineError::Grouping { message, .. }
+            | PipelineError::Serialization { message, .. }
+            | PipelineError::EngineLaunch { message, .. }
+            | PipelineError::Aggregation { message, .. }
+            | PipelineError::InvalidOption { message, .. } => write!(f, "{message}"),
+            PipelineError::EngineFailure { message, stderr, .. } if stderr.is_empty() => write!(f, "{message}"),
+            PipelineError::EngineFailure { message, stderr, .. } => write!(f, "{message}: {stderr}"),
+            PipelineError::EngineTimeout { seconds, .. } => {
+                write!(f, "The engine didn't finish within {seconds} seconds")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
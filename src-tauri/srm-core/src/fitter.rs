@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::path::Path;
+use async_trait::async_trait;
+use crate::aggregator::{Calculation, Reading};
+use crate::engine::Engine;
+use crate::parser::{Day, Peptide};
+use crate::serializer::Dataset;
+
+// Natural abundance of deuterium among hydrogen atoms
+const NATURAL_DEUTERIUM: f64 = 1.5574e-4;
+
+// Spacing between neighbouring isotopologue peaks at charge 1
+const ISOTOPE_SPACING: f64 = 1.00335;
+
+// Rate constants are searched for on a log scale between these bounds (per day)
+const MIN_RATE: f64 = 1e-4;
+const MAX_RATE: f64 = 10.0;
+const GRID_SIZE: usize = 200;
+const REFINE_ITERATIONS: usize = 60;
+// How close (in log units) a fitted rate may get to the search bounds before it's rejected
+const EDGE_TOLERANCE: f64 = 1e-6;
+
+// Printed by SRM_Rate.exe for results it couldn't compute
+pub const NAN_TEXT: &str = "-nan(ind)";
+
+/// Fits the rate constants in process instead of launching SRM_Rate.exe.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeEngine;
+
+pub struct PeptideFit {
+    protein: String,
+    peptide: String,
+    neh: f64,
+    charge: u64,
+    points: Vec<Point>,
+    rate: f64,
+    mpe_0: f64,
+    mpe_1: f64,
+    replicates: Vec<f64>,
+}
+
+#[async_trait]
+impl Engine for NativeEngine {
+    type Job = Vec<PeptideFit>;
+
+    fn name(&self) -> String {
+        "Native".to_string()
+    }
+
+    async fn prepare(&self, _work_dir: &Path, dataset: &Dataset) -> anyhow::Result<Vec<PeptideFit>> {
+        // Body water enrichment is given in percent, unlabeled samples count as zero
+        let enrichments: Vec<f64> = dataset.samples.iter()
+            .map(|sample| sample.label.parse::<f64>().unwrap_or(0.0) / 100.0)
+            .collect();
+
+        let days = dataset.days();
+
+        // Each peptide name appears in at most one charge group per dataset
+        let mut order: Vec<String> = vec![];
+        let mut transitions: HashMap<String, Vec<Peptide>> = HashMap::new();
+        for peptide in &dataset.peptides {
+            if !transitions.contains_key(&peptide.name) {
+                order.push(peptide.name.clone());
+            }
+            transitions.entry(peptide.name.clone()).or_default().push(peptide.clone());
+        }
+
+        Ok(order.iter()
+            .map(|name| prepare_peptide(&days, &enrichments, transitions.remove(name).unwrap()))
+            .collect())
+    }
+
+    async fn run(&self, job: &mut Vec<PeptideFit>) -> anyhow::Result<()> {
+        let mut fits = std::mem::take(job);
+
+        // Fitting is CPU bound, keep it off the async workers so datasets run in parallel
+        *job = tokio::task::spawn_blocking(move || {
+            for fit in fits.iter_mut() {
+                fit_peptide(fit);
+            }
+            fits
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn collect(&self, job: Vec<PeptideFit>, dataset: &Dataset) -> anyhow::Result<Vec<Calculation>> {
+        Ok(job.into_iter()
+            .map(|fit| to_calculation(fit, dataset))
+            .collect())
+    }
+}
+
+fn prepare_peptide(days: &[Day], enrichments: &[f64], mut transitions: Vec<Peptide>) -> PeptideFit {
+    transitions.sort_by(|a, b| a.mass_charge_ratio.total_cmp(&b.mass_charge_ratio));
+
+    PeptideFit {
+        protein: transitions[0].protein.clone(),
+        peptide: transitions[0].name.trim().to_string(),
+        neh: labeling_sites(&transitions[0].name),
+        charge: charge_state(&transitions),
+        points: monoisotopic_fractions(days, enrichments, &transitions),
+        rate: f64::NAN,
+        mpe_0: f64::NAN,
+        mpe_1: f64::NAN,
+        replicates: vec![],
+    }
+}
+
+fn fit_peptide(fit: &mut PeptideFit) {
+    let neh = fit.neh;
+    let points = &fit.points;
+
+    let informative = points.iter().filter(|point| point.time > 0.0 && point.enrichment > 0.0).count();
+    if informative < 2 {
+        return;
+    }
+
+    fit.rate = fit_rate(neh, points);
+    if !fit.rate.is_finite() {
+        return;
+    }
+
+    fit.mpe_0 = mean_percent_error(neh, fit.rate, points);
+    fit.mpe_1 = mean_percent_error(neh, 0.0, points);
+
+    // Leave-one-out estimates give the spread of the rate constant
+    if points.len() > 2 {
+        fit.replicates = (0..points.len())
+            .map(|skipped| {
+                let subset: Vec<Point> = points.iter().enumerate()
+                    .filter(|(i, _)| *i != skipped)
+                    .map(|(_, point)| *point)
+                    .collect();
+                fit_rate(neh, &subset)
+            })
+            .filter(|replicate| replicate.is_finite())
+            .collect();
+    }
+}
+
+fn to_calculation(fit: PeptideFit, dataset: &Dataset) -> Calculation {
+    let replicates = &fit.replicates;
+
+    let (two_sd_minus, two_sd_plus) = if replicates.len() > 1 {
+        let n = replicates.len() as f64;
+        let mean = replicates.iter().sum::<f64>() / n;
+        let variance = replicates.iter().map(|r| (r - mean).powi(2)).sum::<f64>() * (n - 1.0) / n;
+        let spread = 2.0 * variance.sqrt();
+        (fit.rate - spread, fit.rate + spread)
+    } else {
+        (f64::NAN, f64::NAN)
+    };
+
+    Calculation {
+        protein: fit.protein,
+        peptide: fit.peptide,
+        neh: Reading::parse(&format_number(fit.neh)),
+        charge: Reading::parse(&fit.charge.to_string()),
+        mean: Reading::parse(&format_number(fit.rate)),
+        n_ret_1: Reading::parse(&fit.points.len().to_string()),
+        mpe_0: Reading::parse(&format_number(fit.mpe_0)),
+        mpe_1: Reading::parse(&format_number(fit.mpe_1)),
+        two_sd_minus: Reading::parse(&format_number(two_sd_minus)),
+        n_ret_2: Reading::parse(&replicates.len().to_string()),
+        two_sd_plus: Reading::parse(&format_number(two_sd_plus)),
+        n_ret_3: Reading::parse(&replicates.len().to_string()),
+        samples_omitted: dataset.samples_omitted.clone(),
+        condition: dataset.condition.clone(),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    time: f64,
+    enrichment: f64,
+    fraction: f64,
+}
+
+// Relative abundance of the lightest transition in every sample where all transitions were measured
+fn monoisotopic_fractions(days: &[Day], enrichments: &[f64], transitions: &[Peptide]) -> Vec<Point> {
+    let mut points = vec![];
+
+    for (i, (&day, &enrichment)) in days.iter().zip(enrichments.iter()).enumerate() {
+        let intensities: Option<Vec<f64>> = transitions.iter()
+            .map(|transition| transition.intensities.get(i).copied().flatten())
+            .collect();
+
+        if let Some(intensities) = intensities {
+            let total: f64 = intensities.iter().sum();
+            if total > 0.0 {
+                points.push(Point {
+                    time: day as f64,
+                    enrichment,
+                    fraction: intensities[0] / total,
+                });
+            }
+        }
+    }
+
+    points
+}
+
+// Fraction of the unlabeled abundance left at a point for a given rate constant
+fn decay(neh: f64, rate: f64, point: &Point) -> f64 {
+    let plateau = (1.0 - point.enrichment / (1.0 - NATURAL_DEUTERIUM)).max(0.0).powf(neh);
+    plateau + (1.0 - plateau) * (-rate * point.time).exp()
+}
+
+// The unlabeled abundance is solved in closed form, which leaves a one dimensional search over the rate
+fn sum_of_squares(neh: f64, rate: f64, points: &[Point]) -> f64 {
+    let (numerator, denominator) = points.iter().fold((0.0, 0.0), |(num, den), point| {
+        let g = decay(neh, rate, point);
+        (num + point.fraction * g, den + g * g)
+    });
+
+    if denominator <= 0.0 {
+        return f64::INFINITY;
+    }
+
+    let baseline = numerator / denominator;
+    points.iter()
+        .map(|point| (point.fraction - baseline * decay(neh, rate, point)).powi(2))
+        .sum()
+}
+
+fn fit_rate(neh: f64, points: &[Point]) -> f64 {
+    if points.len() < 2 {
+        return f64::NAN;
+    }
+
+    let (low, high) = (MIN_RATE.ln(), MAX_RATE.ln());
+    let step = (high - low) / (GRID_SIZE - 1) as f64;
+
+    let best = (0..GRID_SIZE)
+        .map(|i| {
+            let log_rate = low + step * i as f64;
+            (log_rate, sum_of_squares(neh, log_rate.exp(), points))
+        })
+        .filter(|(_, sse)| sse.is_finite())
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+    let (mut a, mut b) = match best {
+        Some((log_rate, _)) => ((log_rate - step).max(low), (log_rate + step).min(high)),
+        None => return f64::NAN,
+    };
+
+    // Golden-section refinement inside the bracket found by the grid
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    for _ in 0..REFINE_ITERATIONS {
+        let c = b - ratio * (b - a);
+        let d = a + ratio * (b - a);
+        if sum_of_squares(neh, c.exp(), points) < sum_of_squares(neh, d.exp(), points) {
+            b = d;
+        } else {
+            a = c;
+        }
+    }
+
+    // A minimum on a bound only says the rate is outside of the range, it isn't a fit
+    let log_rate = (a + b) / 2.0;
+    if log_rate - low < EDGE_TOLERANCE || high - log_rate < EDGE_TOLERANCE {
+        return f64::NAN;
+    }
+
+    log_rate.exp()
+}
+
+fn mean_percent_error(neh: f64, rate: f64, points: &[Point]) -> f64 {
+    let (numerator, denominator) = points.iter().fold((0.0, 0.0), |(num, den), point| {
+        let g = decay(neh, rate, point);
+        (num + point.fraction * g, den + g * g)
+    });
+    let baseline = numerator / denominator;
+
+    let errors: Vec<f64> = points.iter()
+        .filter(|point| point.fraction > 0.0)
+        .map(|point| ((baseline * decay(neh, rate, point) - point.fraction) / point.fraction).abs() * 100.0)
+        .collect();
+
+    errors.iter().sum::<f64>() / errors.len() as f64
+}
+
+// The charge column of the input when it has one, otherwise guessed from the isotope spacing
+fn charge_state(transitions: &[Peptide]) -> u64 {
+    if let Some(charge) = transitions[0].charge.filter(|&charge| charge > 0) {
+        return charge.into();
+    }
+    if transitions.len() < 2 {
+        return 1;
+    }
+
+    let gap = transitions[1].mass_charge_ratio - transitions[0].mass_charge_ratio;
+    if gap <= 0.0 {
+        return 1;
+    }
+
+    (ISOTOPE_SPACING / gap).round().max(1.0) as u64
+}
+
+// Number of exchangeable hydrogens, summed from per-residue labeling sites
+fn labeling_sites(sequence: &str) -> f64 {
+    sequence.chars()
+        .map(|residue| match residue {
+            'A' => 4.00,
+            'R' => 3.43,
+            'N' => 1.89,
+            'D' => 1.89,
+            'C' => 1.62,
+            'Q' => 3.95,
+            'E' => 3.95,
+            'G' => 2.06,
+            'H' => 2.88,
+            'I' => 1.00,
+            'L' => 0.60,
+            'K' => 0.54,
+            'M' => 1.12,
+            'F' => 0.32,
+            'P' => 2.59,
+            'S' => 2.61,
+            'T' => 0.20,
+            'W' => 0.08,
+            'Y' => 0.42,
+            'V' => 0.56,
+            _ => 0.0,
+        })
+        .sum()
+}
+
+fn format_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:.6}", value).trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        NAN_TEXT.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(mass_charge_ratio: f64) -> Peptide {
+        Peptide {
+            name: "LVTDLTK".to_string(),
+            protein: "Bsa".to_string(),
+            mass_charge_ratio,
+            charge: None,
+            intensities: vec![],
+        }
+    }
+
+    // Monoisotopic fractions following the model exactly for a rate constant
+    fn synthetic_points(neh: f64, rate: f64, baseline: f64) -> Vec<Point> {
+        [0.0, 1.0, 2.0, 4.0, 7.0, 14.0, 21.0, 28.0].iter()
+            .map(|&time| {
+                let enrichment = if time == 0.0 { 0.0 } else { 0.04 };
+                let point = Point { time, enrichment, fraction: 0.0 };
+                Point { fraction: baseline * decay(neh, rate, &point), ..point }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn labeling_sites_sum_the_residues() {
+        assert!((labeling_sites("AK") - 4.54).abs() < 1e-9);
+        assert!((labeling_sites("LVTDLTK") - 4.59).abs() < 1e-9);
+        // Modifications and unknown residues don't add sites
+        assert!((labeling_sites("A[+57]K") - labeling_sites("AK")).abs() < 1e-9);
+        assert_eq!(labeling_sites(""), 0.0);
+    }
+
+    #[test]
+    fn charge_state_follows_the_isotope_spacing() {
+        assert_eq!(charge_state(&[transition(395.239461), transition(395.740957)]), 2);
+        assert_eq!(charge_state(&[transition(687.670095), transition(688.004396)]), 3);
+        assert_eq!(charge_state(&[transition(500.0), transition(501.00335)]), 1);
+        assert_eq!(charge_state(&[transition(500.0)]), 1);
+        assert_eq!(charge_state(&[transition(500.0), transition(500.0)]), 1);
+    }
+
+    #[test]
+    fn charge_state_prefers_the_charge_column() {
+        let charged = |mass_charge_ratio, charge| Peptide { charge, ..transition(mass_charge_ratio) };
+        assert_eq!(charge_state(&[charged(395.239461, Some(3)), charged(395.740957, Some(3))]), 3);
+        assert_eq!(charge_state(&[charged(500.0, Some(4))]), 4);
+        assert_eq!(charge_state(&[charged(395.239461, Some(0)), charged(395.740957, Some(0))]), 2);
+    }
+
+    #[test]
+    fn decay_goes_from_one_to_the_plateau() {
+        let unlabeled = Point { time: 5.0, enrichment: 0.0, fraction: 0.0 };
+        assert!((decay(20.0, 0.1, &unlabeled) - 1.0).abs() < 1e-12);
+
+        let start = Point { time: 0.0, enrichment: 0.04, fraction: 0.0 };
+        assert!((decay(20.0, 0.1, &start) - 1.0).abs() < 1e-12);
+
+        let plateau = (1.0 - 0.04 / (1.0 - NATURAL_DEUTERIUM)).powf(20.0);
+        let late = Point { time: 1000.0, ..start };
+        assert!((decay(20.0, 0.1, &late) - plateau).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_rate_recovers_a_known_rate() {
+        for rate in [0.005, 0.05, 0.3] {
+            let fitted = fit_rate(20.0, &synthetic_points(20.0, rate, 0.6));
+            assert!((fitted - rate).abs() / rate < 1e-3, "expected {rate}, got {fitted}");
+        }
+    }
+
+    #[test]
+    fn fit_rate_rejects_rates_outside_the_search_range() {
+        // No labeling at all puts the minimum on the lower bound
+        let points = synthetic_points(20.0, 0.0, 0.6);
+        assert!(fit_rate(20.0, &points).is_nan());
+
+        let points = synthetic_points(20.0, 1000.0, 0.6);
+        assert!(fit_rate(20.0, &points).is_nan());
+    }
+
+    #[test]
+    fn fit_peptide_needs_two_informative_points() {
+        let points = synthetic_points(20.0, 0.05, 0.6);
+        let mut fit = PeptideFit {
+            protein: "Bsa".to_string(),
+            peptide: "LVTDLTK".to_string(),
+            neh: 20.0,
+            charge: 2,
+            // The unlabeled point and a single labeled one
+            points: points[..2].to_vec(),
+            rate: f64::NAN,
+            mpe_0: f64::NAN,
+            mpe_1: f64::NAN,
+            replicates: vec![],
+        };
+
+        fit_peptide(&mut fit);
+        assert!(fit.rate.is_nan());
+        assert!(fit.replicates.is_empty());
+
+        fit.points = points;
+        fit_peptide(&mut fit);
+        assert!((fit.rate - 0.05).abs() < 1e-4);
+        assert_eq!(fit.replicates.len(), fit.points.len());
+    }
+
+    #[test]
+    fn format_number_matches_the_engine() {
+        assert_eq!(format_number(0.0123456789), "0.012346");
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(f64::NAN), NAN_TEXT);
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use crate::parser::Peptide;
+
+#[derive(Debug, Clone)]
+pub struct PeptideGroup {
+    pub peptides: Vec<Peptide>,
+    pub na_columns: Vec<bool>,
+}
+
+// Mass difference between the 13C and 12C isotopes
+const ISOTOPE_SPACING: f64 = 1.003355;
+const MAX_CHARGE: u32 = 6;
+
+/// How the transitions of a peptide are split into charge states.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum GroupingStrategy {
+    /// Splits where the gap in m/z exceeds the standard deviation of the group times the
+    /// tolerance multiplier.
+    #[default]
+    Heuristic,
+    /// Groups by the charge column of the input. Peptides without a charge fall back to the
+    /// heuristic.
+    Charge,
+    /// Keeps transitions together while they're one isotope apart for a charge state, within
+    /// `tolerance` parts per million of their m/z.
+    Ppm { tolerance: f64 },
+}
+
+/// How a transition was placed relative to the previous transition of the same peptide.
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitDecision {
+    /// Distance in m/z to the previous transition
+    pub gap: f64,
+    pub rule: SplitRule,
+    pub joined: bool,
+}
+
+/// What a [`SplitDecision`] was based on.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum SplitRule {
+    /// Joined when the gap is below the standard deviation of the group times the tolerance
+    /// multiplier. `None` while the group has a single transition, which the next one always joins.
+    Threshold { threshold: Option<f64> },
+    /// Joined when the gap is within `min..=max`, one isotope spacing for `charge` give or take
+    /// the ppm tolerance.
+    IsotopeSpacing { charge: u32, min: f64, max: f64 },
+    /// Split because the gap isn't one isotope spacing for any charge.
+    NoIsotopeSpacing,
+    /// Joined when the charge column gives both transitions the same charge, that of this one.
+    ChargeColumn { charge: Option<u32> },
+}
+
+pub fn group_by_peptides(tolerance_multiplier: f64, peptides: Vec<Peptide>) -> Vec<PeptideGroup> {
+    group_by_strategy(GroupingStrategy::Heuristic, tolerance_multiplier, peptides)
+}
+
+pub fn group_by_strategy(
+    strategy: GroupingStrategy,
+    tolerance_multiplier: f64,
+    peptides: Vec<Peptide>,
+) -> Vec<PeptideGroup> {
+    explain_group_by_peptides(strategy, tolerance_multiplier, peptides)
+        .into_iter()
+        .map(|(group, _)| group)
+        .collect()
+}
+
+/// Same as [`group_by_strategy`], along with the decision taken for each transition of each
+/// group. The first transition of a peptide has no decision.
+pub fn explain_group_by_peptides(
+    strategy: GroupingStrategy,
+    tolerance_multiplier: f64,
+    peptides: Vec<Peptide>,
+) -> Vec<(PeptideGroup, Vec<Option<SplitDecision>>)> {
+    let mut sorted_peptides = peptides;
+    sorted_peptides.sort_by(|a, b| {
+        let charge = |peptide: &Peptide| match strategy {
+            GroupingStrategy::Charge => peptide.charge,
+            _ => None,
+        };
+
+        a.name.cmp(&b.name)
+            .then_with(|| charge(a).cmp(&charge(b)))
+            // The parser drops non-finite m/z values, total_cmp keeps any that get here from
+            // panicking and puts NaNs in groups of their own
+            .then_with(|| a.mass_charge_ratio.total_cmp(&b.mass_charge_ratio))
+    });
+
+    let mut groups = Vec::new();
+    let mut current_group: Vec<Peptide> = Vec::new();
+    let mut decisions: Vec<Option<SplitDecision>> = Vec::new();
+
+    for peptide in sorted_peptides {
+        if current_group.is_empty() {
+            current_group.push(peptide);
+            decisions.push(None);
+        } else if peptide.name == current_group.last().unwrap().name {
+            let decision = match strategy {
+                GroupingStrategy::Heuristic => heuristic_decision(tolerance_multiplier, &current_group, &peptide),
+                GroupingStrategy::Charge => charge_decision(tolerance_multiplier, &current_group, &peptide),
+                GroupingStrategy::Ppm { tolerance } => ppm_decision(tolerance, &current_group, &peptide),
+            };
+
+            if decision.joined {
+                current_group.push(peptide);
+                decisions.push(Some(decision));
+            } else {
+                groups.push((create_peptide_group(&current_group), decisions));
+                current_group = vec![peptide];
+                decisions = vec![Some(decision)];
+            }
+        } else {
+            // Different name, finalize current group and start a new one
+            groups.push((create_peptide_group(&current_group), decisions));
+            current_group = vec![peptide];
+            decisions = vec![None];
+        }
+    }
+
+    if !current_group.is_empty() {
+        groups.push((create_peptide_group(&current_group), decisions));
+    }
+
+    groups
+}
+
+fn heuristic_decision(tolerance_multiplier: f64, group: &[Peptide], peptide: &Peptide) -> SplitDecision {
+    // Dynamically determine the threshold based on the current group
+    let threshold = calc_std_deviation(group) * tolerance_multiplier;
+    let gap = (peptide.mass_charge_ratio - group.last().unwrap().mass_charge_ratio).abs();
+
+    SplitDecision {
+        gap,
+        rule: SplitRule::Threshold { threshold: Some(threshold).filter(|threshold| threshold.is_finite()) },
+        joined: gap < threshold,
+    }
+}
+
+fn charge_decision(tolerance_multiplier: f64, group: &[Peptide], peptide: &Peptide) -> SplitDecision {
+    let last = group.last().unwrap();
+    match (last.charge, peptide.charge) {
+        (None, None) => heuristic_decision(tolerance_multiplier, group, peptide),
+        (last_charge, charge) => SplitDecision {
+            gap: (peptide.mass_charge_ratio - last.mass_charge_ratio).abs(),
+            rule: SplitRule::ChargeColumn { charge },
+            joined: charge.is_some() && charge == last_charge,
+        },
+    }
+}
+
+// The charge of a group is the one whose isotope spacing matches its first gap
+fn ppm_decision(tolerance: f64, group: &[Peptide], peptide: &Peptide) -> SplitDecision {
+    let last = group.last().unwrap();
+    let gap = (peptide.mass_charge_ratio - last.mass_charge_ratio).abs();
+    let allowed = peptide.mass_charge_ratio.abs() * tolerance * 1e-6;
+
+    let charge = match group {
+        [first, second, ..] => isotope_charge((second.mass_charge_ratio - first.mass_charge_ratio).abs(), allowed),
+        _ => isotope_charge(gap, allowed),
+    };
+
+    match charge {
+        Some(charge) => {
+            let spacing = ISOTOPE_SPACING / charge as f64;
+            let (min, max) = (spacing - allowed, spacing + allowed);
+            SplitDecision {
+                gap,
+                rule: SplitRule::IsotopeSpacing { charge, min, max },
+                joined: (min..=max).contains(&gap),
+            }
+        }
+        None => SplitDecision {
+            gap,
+            rule: SplitRule::NoIsotopeSpacing,
+            joined: false,
+        },
+    }
+}
+
+fn isotope_charge(gap: f64, allowed: f64) -> Option<u32> {
+    (1..=MAX_CHARGE).find(|&charge| (gap - ISOTOPE_SPACING / charge as f64).abs() <= allowed)
+}
+
+
+fn calc_std_deviation(peptides: &[Peptide]) -> f64 {
+    if peptides.len() <= 1 {
+        return f64::INFINITY; // If there's only one peptide, no need for a threshold.
+    }
+
+    let mean: f64 = peptides.iter().map(|p| p.mass_charge_ratio).sum::<f64>() / peptides.len() as f64;
+    let variance: f64 = peptides.iter()
+        .map(|p| (p.mass_charge_ratio - mean).powi(2))
+        .sum::<f64>() / (peptides.len() - 1) as f64;
+    let std_deviation = variance.sqrt();
+
+    std_deviation * 2.0 // You might want to scale this value based on your data.
+}
+
+
+// Helper function to create a PeptideGroup from a vector of peptides
+fn create_peptide_group(peptides: &[Peptide]) -> PeptideGroup {
+    let num_columns = peptides.iter().map(|peptide| peptide.intensities.len()).max().unwrap_or(0);
+    let mut na_columns = vec![false; num_columns];
+
+    // Check each column for NA values
+    for peptide in peptides {
+        for (i, intensity) in peptide.intensities.iter().enumerate() {
+            if intensity.is_none() {
+                na_columns[i] = true;
+            }
+        }
+    }
+
+    PeptideGroup { peptides: peptides.to_vec(), na_columns }
+}
+
+#[derive(Debug, Clone)]
+pub struct NAGroup {
+    pub peptides: Vec<Peptide>,
+    pub na_columns: Vec<bool>,
+}
+
+pub fn group_by_na_columns(groups: Vec<PeptideGroup>) -> Vec<NAGroup> {
+    let assignments = assign_na_groups(&groups);
+    let mut groups: Vec<Option<PeptideGroup>> = groups.into_iter().map(Some).collect();
+
+    assignments.into_iter().map(|(na_columns, members)| {
+        let peptides = members.into_iter()
+            .filter_map(|i| groups[i].take())
+            .flat_map(|group| group.peptides)
+            .collect();
+        NAGroup { peptides, na_columns }
+    }).collect()
+}
+
+/// The NA column pattern of each NA group and the indices of the peptide groups merged into it,
+/// in the order the NA groups were created. Groups with the same NA columns are merged as long
+/// as no two of them are the same peptide.
+pub fn assign_na_groups(groups: &[PeptideGroup]) -> Vec<(Vec<bool>, Vec<usize>)> {
+    let mut na_groups: Vec<(Vec<bool>, Vec<usize>)> = vec![];
+    let mut indices: HashMap<(Vec<bool>, u64), usize> = HashMap::new();
+
+    for (i, group) in groups.iter().enumerate() {
+        let name = group.peptides.first().map(|peptide| &peptide.name);
+
+        let mut count = 1;
+        loop {
+            let key = (group.na_columns.clone(), count);
+            match indices.get(&key) {
+                Some(&j) if na_groups[j].1.iter().any(|&k| groups[k].peptides.iter().any(|x| Some(&x.name) == name)) => {
+                    count += 1;
+                }
+                Some(&j) => {
+                    na_groups[j].1.push(i);
+                    break;
+                }
+                None => {
+                    indices.insert(key, na_groups.len());
+                    na_groups.push((group.na_columns.clone(), vec![i]));
+                    break;
+                }
+            }
+        }
+    }
+
+    na_groups
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn mass_charge_ratio() -> impl Strategy<Value = f64> {
+        prop_oneof![
+            4 => 100.0..2000.0f64,
+            1 => any::<f64>(),
+            1 => Just(f64::NAN),
+            1 => Just(f64::INFINITY),
+        ]
+    }
+
+    fn peptide() -> impl Strategy<Value = Peptide> {
+        (
+            prop::sample::select(vec!["A", "B", "C"]),
+            mass_charge_ratio(),
+            prop::option::of(0u32..5),
+            prop::collection::vec(prop::option::of(any::<f64>()), 0..6),
+        ).prop_map(|(name, mass_charge_ratio, charge, intensities)| Peptide {
+            name: name.to_string(),
+            protein: "P".to_string(),
+            mass_charge_ratio,
+            charge,
+            intensities,
+        })
+    }
+
+    fn grouping_strategy() -> impl Strategy<Value = GroupingStrategy> {
+        prop_oneof![
+            Just(GroupingStrategy::Heuristic),
+            Just(GroupingStrategy::Charge),
+            any::<f64>().prop_map(|tolerance| GroupingStrategy::Ppm { tolerance }),
+        ]
+    }
+
+    fn transition(mass_charge_ratio: f64, charge: Option<u32>) -> Peptide {
+        Peptide {
+            name: "LVTDLTK".to_string(),
+            protein: "Bsa".to_string(),
+            mass_charge_ratio,
+            charge,
+            intensities: vec![Some(1.0)],
+        }
+    }
+
+    #[test]
+    fn ppm_decisions_hold_the_isotope_window() {
+        let peptides = vec![transition(500.0, None), transition(500.0 + ISOTOPE_SPACING / 2.0, None), transition(502.0, None)];
+        let groups = explain_group_by_peptides(GroupingStrategy::Ppm { tolerance: 10.0 }, 2.0, peptides);
+        assert_eq!(groups.len(), 2);
+
+        let decision = groups[0].1[1].as_ref().unwrap();
+        let SplitRule::IsotopeSpacing { charge, min, max } = decision.rule else { panic!("{decision:?}") };
+        assert_eq!(charge, 2);
+        assert!(decision.joined && min < decision.gap && decision.gap < max);
+        assert!((max - min - 2.0 * (500.0 + ISOTOPE_SPACING / 2.0) * 10.0 * 1e-6).abs() < 1e-9);
+
+        let decision = groups[1].1[0].as_ref().unwrap();
+        assert!(!decision.joined);
+        assert!(matches!(decision.rule, SplitRule::IsotopeSpacing { charge: 2, .. }));
+    }
+
+    #[test]
+    fn charge_decisions_dont_compare_gaps() {
+        let peptides = vec![transition(500.0, Some(2)), transition(900.0, Some(2)), transition(500.1, Some(3))];
+        let groups = explain_group_by_peptides(GroupingStrategy::Charge, 2.0, peptides);
+        assert_eq!(groups.len(), 2);
+
+        let decision = groups[0].1[1].as_ref().unwrap();
+        assert!(decision.joined && matches!(decision.rule, SplitRule::ChargeColumn { charge: Some(2) }));
+        let decision = groups[1].1[0].as_ref().unwrap();
+        assert!(!decision.joined && matches!(decision.rule, SplitRule::ChargeColumn { charge: Some(3) }));
+    }
+
+    proptest! {
+        #[test]
+        fn grouping_never_panics(
+            strategy in grouping_strategy(),
+            tolerance_multiplier in any::<f64>(),
+            peptides in prop::collection::vec(peptide(), 0..40),
+        ) {
+            let groups = group_by_strategy(strategy, tolerance_multiplier, peptides.clone());
+            let na_groups = group_by_na_columns(groups);
+
+            let grouped: usize = na_groups.iter().map(|group| group.peptides.len()).sum();
+            prop_assert_eq!(grouped, peptides.len());
+        }
+
+        #[test]
+        fn groups_hold_a_single_peptide(
+            strategy in grouping_strategy(),
+            peptides in prop::collection::vec(peptide(), 0..40),
+        ) {
+            for (group, decisions) in explain_group_by_peptides(strategy, 2.0, peptides) {
+                prop_assert!(!group.peptides.is_empty());
+                prop_assert!(group.peptides.iter().all(|peptide| peptide.name == group.peptides[0].name));
+                prop_assert_eq!(decisions.len(), group.peptides.len());
+            }
+        }
+
+        #[test]
+        fn na_groups_tolerate_empty_peptide_groups(
+            lengths in prop::collection::vec(0usize..3, 0..10),
+        ) {
+            let groups: Vec<PeptideGroup> = lengths.iter()
+                .map(|&length| PeptideGroup { peptides: vec![], na_columns: vec![false; length] })
+                .collect();
+
+            prop_assert_eq!(group_by_na_columns(groups).iter().map(|group| group.peptides.len()).sum::<usize>(), 0);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Processing pipeline shared by the GUI and the command line.
+//!
+//! A spreadsheet goes through [`parser::parse`], is grouped with
+//! [`grouper::group_by_peptides`] and [`grouper::group_by_na_columns`], split into datasets by
+//! [`serializer::prepare_datasets`], run through an [`engine::Engine`] by
+//! [`analyzer::analyze_all`] and combined by [`aggregator::aggregate`]. Runs split by condition
+//! can then be compared with [`comparator::compare`]. [`process_file`] runs every stage for one
+//! input file, [`validate`] checks an input file without processing it and
+//! [`explain_grouping`] shows how its peptides would be grouped.
+
+pub mod parser;
+pub mod grouper;
+pub mod serializer;
+pub mod analyzer;
+pub mod aggregator;
+pub mod comparator;
+pub mod engine;
+pub mod error;
+pub mod fitter;
+pub mod pool;
+pub mod preview;
+pub mod processor;
+pub mod progress;
+pub mod runner;
+pub mod validator;
+mod util;
+
+pub use aggregator::ResultOrder;
+pub use comparator::{Comparison, ComparisonMethod, compare};
+pub use engine::{builtin_engine, DynEngine, Engine, ExecutableEngine};
+pub use error::PipelineError;
+pub use fitter::NativeEngine;
+pub use grouper::GroupingStrategy;
+pub use parser::{EngineType, InputFile, ParseProblem, ParseWarning, Sample, Sheet};
+pub use pool::WorkerPool;
+pub use preview::{explain_grouping, GroupingPreview};
+pub use processor::{process_file, ProcessOptions, ProcessSummary};
+pub use progress::{ProgressCallback, ProgressUpdate};
+pub use runner::Runner;
+pub use serializer::{DerivedMetric, OutputFormat};
+pub use validator::{InputProblem, Severity, validate};
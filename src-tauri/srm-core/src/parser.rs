@@ -0,0 +1,714 @@
+use std::fmt;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use calamine::{Data, open_workbook_auto_from_rs, Reader as WorkbookReader};
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use tokio::fs;
+use crate::error::PipelineError;
+use crate::util::serde::deserialize_path;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct InputFile {
+    pub uuid: String,
+    #[serde(deserialize_with = "deserialize_path")]
+    pub path: PathBuf,
+    #[serde(default)]
+    pub sheet: Option<Sheet>,
+}
+
+/// Worksheet to read from a workbook, by position (starting at 0) or by name.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Sheet {
+    Index(usize),
+    Name(String),
+}
+
+impl Sheet {
+    pub fn parse(value: &str) -> Self {
+        match value.parse::<usize>() {
+            Ok(index) => Sheet::Index(index),
+            Err(_) => Sheet::Name(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineType {
+    Single,
+    Multi,
+    Native,
+}
+
+pub type Day = u64;
+
+pub type Mouse = String;
+
+pub type Label = String;
+
+/// One sample column of the input and everything the header rows say about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Heading of the column in the Protein header row
+    pub name: String,
+    pub day: Day,
+    pub mouse: Mouse,
+    pub label: Label,
+    /// Values of the other header rows, such as condition or sex, by row label in input order
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Sample {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(attribute, _)| same_label(attribute, name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Peptide {
+    pub name: String,
+    pub protein: String,
+    pub mass_charge_ratio: f64,
+    /// Precursor charge, when the input has a charge column
+    pub charge: Option<u32>,
+    pub intensities: Vec<Option<f64>>,
+}
+
+/// A cell that couldn't be read, with 1-based row and column numbers.
+#[derive(Debug, Clone)]
+pub struct ParseWarning {
+    pub row: usize,
+    pub column: usize,
+    pub value: String,
+    pub problem: ParseProblem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseProblem {
+    /// The intensity was treated as missing
+    Intensity,
+    /// The m/z isn't a finite number, the row was skipped
+    MassChargeRatio,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problem {
+            ParseProblem::Intensity => write!(
+                f,
+                "Row {}, column {}: couldn't read intensity \"{}\", it was treated as missing",
+                self.row, self.column, self.value,
+            ),
+            ParseProblem::MassChargeRatio => write!(
+                f,
+                "Row {}, column {}: couldn't read m/z \"{}\", the row was skipped",
+                self.row, self.column, self.value,
+            ),
+        }
+    }
+}
+
+// Cells of a row along with its line number in the file
+pub(crate) type Row = (usize, Vec<String>);
+
+const WORKBOOK_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// Reads a CSV file, or a sheet of an Excel/OpenDocument workbook (the first one unless `sheet`
+/// says otherwise).
+///
+/// The Day, Mouse, Body water enrichment and Protein header rows are found by their label in
+/// column 0, in any order and next to other metadata rows. Sample columns are the ones with a day.
+///
+/// Empty and `#N/A` intensities are missing values. Any other intensity that isn't a number is
+/// treated as missing too and reported in the returned warnings, as are rows skipped because their
+/// m/z isn't a finite number.
+pub async fn parse(spreadsheet: &Path, sheet: Option<&Sheet>)
+                   -> Result<(Vec<Sample>, Vec<Peptide>, Vec<ParseWarning>), PipelineError>
+{
+    let mut rows = read_rows(spreadsheet, sheet).await?.into_iter();
+
+    let layout = Layout::locate(spreadsheet, &mut rows)?;
+    let samples = extract_samples(spreadsheet, &layout)?;
+    let (peptides, warnings) = extract_peptides(&layout, &mut rows);
+
+    Ok((samples, peptides, warnings))
+}
+
+fn parse_error(path: &Path, row: Option<usize>, column: Option<usize>, message: impl Into<String>) -> PipelineError {
+    PipelineError::Parse {
+        path: path.to_path_buf(),
+        row,
+        column,
+        message: message.into(),
+    }
+}
+
+pub(crate) async fn read_rows(spreadsheet: &Path, sheet: Option<&Sheet>) -> Result<Vec<Row>, PipelineError> {
+    let contents = fs::read(spreadsheet).await
+        .map_err(|e| parse_error(spreadsheet, None, None, format!("Failed to read file: {e}")))?;
+
+    let is_workbook = spreadsheet.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| WORKBOOK_EXTENSIONS.contains(&extension.as_str()));
+
+    if is_workbook {
+        read_workbook(spreadsheet, contents, sheet)
+    } else {
+        read_csv(spreadsheet, contents)
+    }
+}
+
+fn read_csv(path: &Path, contents: Vec<u8>) -> Result<Vec<Row>, PipelineError> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(Cursor::new(&contents));
+
+    rdr.records()
+        .map(|result| {
+            let record = result.map_err(|e| {
+                let row = e.position().map(|position| position.line() as usize);
+                parse_error(path, row, None, "Failed to read row from spreadsheet")
+            })?;
+            // The reader's line count is off for CRLF files, where records can start on the previous
+            // terminator, so skip line breaks from the byte offset and count the ones before it
+            let mut offset = record.position().map_or(0, |position| position.byte() as usize);
+            while matches!(contents.get(offset), Some(b'\r' | b'\n')) {
+                offset += 1;
+            }
+            let line = contents[..offset].iter().filter(|&&byte| byte == b'\n').count() + 1;
+            Ok((line, record.iter().map(|field| field.to_string()).collect()))
+        })
+        .collect()
+}
+
+fn read_workbook(path: &Path, contents: Vec<u8>, sheet: Option<&Sheet>) -> Result<Vec<Row>, PipelineError> {
+    let error = |message: String| parse_error(path, None, None, message);
+
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(contents))
+        .map_err(|e| error(format!("Failed to open workbook: {e}")))?;
+
+    let range = match sheet {
+        Some(Sheet::Name(name)) => workbook.worksheet_range(name)
+            .map_err(|_| error(format!("Sheet \"{name}\" doesn't exist in the workbook")))?,
+        Some(Sheet::Index(index)) => workbook.worksheet_range_at(*index)
+            .ok_or_else(|| error(format!("The workbook doesn't have a sheet at position {index}")))?
+            .map_err(|e| error(format!("Failed to read sheet: {e}")))?,
+        None => workbook.worksheet_range_at(0)
+            .ok_or_else(|| error("The workbook doesn't have any sheets".to_string()))?
+            .map_err(|e| error(format!("Failed to read sheet: {e}")))?,
+    };
+
+    // Ranges start at the first used cell, pad them so columns line up with the CSV layout
+    let (first_row, first_column) = range.start().map_or((0, 0), |(row, column)| (row as usize, column as usize));
+
+    Ok(range.rows()
+        .enumerate()
+        .map(|(i, row)| {
+            let cells = std::iter::repeat_n(String::new(), first_column)
+                .chain(row.iter().map(cell_to_string))
+                .collect();
+            (first_row + i + 1, cells)
+        })
+        .collect())
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(value) => value.clone(),
+        // Whole numbers are stored as floats, print them the way a CSV export would
+        Data::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => format!("{value:.0}"),
+        Data::Float(value) => value.to_string(),
+        Data::Int(value) => value.to_string(),
+        Data::Bool(value) => value.to_string(),
+        Data::Error(_) => "#N/A".to_string(),
+        Data::DateTime(value) => value.as_f64().to_string(),
+        Data::DateTimeIso(value) | Data::DurationIso(value) => value.clone(),
+    }
+}
+
+/// Where the data is in the input, found from the header rows.
+///
+/// Header rows are recognised by their label in column 0 and can come in any order, rows with
+/// other labels above the Protein header row are kept as extra metadata. The sample columns are
+/// the ones with a day, which lets annotation columns sit between the peptide columns and the
+/// intensities.
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+    /// Rows above the Protein header row with their label, in input order
+    pub header_rows: Vec<(String, Row)>,
+    pub protein_row: usize,
+    /// Cells of the Protein header row
+    pub headings: Vec<String>,
+    pub protein: usize,
+    pub peptide: usize,
+    pub mass_charge_ratio: usize,
+    pub charge: Option<usize>,
+    pub samples: Vec<usize>,
+}
+
+pub(crate) const DAY_LABEL: &str = "Day";
+pub(crate) const MOUSE_LABEL: &str = "Mouse";
+pub(crate) const ENRICHMENT_LABEL: &str = "Body water enrichment";
+pub(crate) const PROTEIN_LABEL: &str = "Protein";
+const CHARGE_HEADINGS: [&str; 3] = ["Charge", "Precursor Charge", "Z"];
+
+fn same_label(a: &str, b: &str) -> bool {
+    a.trim().eq_ignore_ascii_case(b.trim())
+}
+
+impl Layout {
+    /// Consumes the rows up to and including the Protein header row.
+    pub(crate) fn locate(path: &Path, rows: &mut impl Iterator<Item = Row>) -> Result<Self, PipelineError> {
+        let mut header_rows: Vec<(String, Row)> = vec![];
+
+        let (protein_row, columns) = loop {
+            let Some((line, record)) = rows.next() else {
+                return Err(parse_error(path, None, None, "Couldn't find the Protein header row"));
+            };
+
+            let label = record.first().map_or("", |label| label.trim());
+            if label.is_empty() {
+                continue;
+            }
+
+            if same_label(label, PROTEIN_LABEL) {
+                break (line, record);
+            }
+
+            header_rows.push((label.to_string(), (line, record)));
+        };
+
+        let column_of = |name: &str, default: usize| {
+            columns.iter().position(|header| same_label(header, name)).unwrap_or(default)
+        };
+        let protein = 0;
+        let peptide = column_of("Peptide", 1);
+        let mass_charge_ratio = column_of("Product Mz", 2);
+        let charge = columns.iter().position(|header| CHARGE_HEADINGS.iter().any(|name| same_label(header, name)));
+
+        let mut layout = Self {
+            header_rows,
+            protein_row,
+            headings: columns.clone(),
+            protein,
+            peptide,
+            mass_charge_ratio,
+            charge,
+            samples: vec![],
+        };
+
+        let (_, days) = layout.header_row(DAY_LABEL)
+            .ok_or_else(|| parse_error(path, None, None, "Couldn't find the Day row"))?;
+
+        // Samples run from the first to the last column with a day, leaving out the peptide columns
+        let filled: Vec<usize> = days.iter().enumerate()
+            .filter(|&(column, day)| !layout.is_peptide_column(column) && !day.trim().is_empty())
+            .map(|(column, _)| column)
+            .collect();
+        let samples = match (filled.first(), filled.last()) {
+            (Some(&first), Some(&last)) => (first..=last).filter(|&column| !layout.is_peptide_column(column)).collect(),
+            _ => vec![],
+        };
+        layout.samples = samples;
+
+        Ok(layout)
+    }
+
+    pub(crate) fn header_row(&self, label: &str) -> Option<&Row> {
+        self.header_rows.iter()
+            .find(|(name, _)| same_label(name, label))
+            .map(|(_, row)| row)
+    }
+
+    /// Whether the column holds the protein, peptide, m/z or charge of the peptide rows.
+    pub(crate) fn is_peptide_column(&self, column: usize) -> bool {
+        [self.protein, self.peptide, self.mass_charge_ratio].contains(&column) || self.charge == Some(column)
+    }
+
+    /// Charge of a peptide row, such as `2` or `2+`. `None` without a charge column or for a value
+    /// that isn't a whole number.
+    pub(crate) fn charge_of(&self, record: &[String]) -> Option<u32> {
+        let value = record.get(self.charge?)?.trim();
+        value.strip_suffix('+').unwrap_or(value).trim().parse().ok()
+    }
+
+    // Values of a header row in the sample columns
+    fn sample_values(&self, record: &[String]) -> Vec<String> {
+        self.samples.iter()
+            .map(|&column| record.get(column).map_or("", |value| value.trim()).to_string())
+            .collect()
+    }
+}
+
+fn extract_peptides(layout: &Layout, rows: &mut impl Iterator<Item = Row>) -> (Vec<Peptide>, Vec<ParseWarning>) {
+    let mut peptides = vec![];
+    let mut warnings = vec![];
+
+    for (line, record) in rows {
+        // Blank rows, like the trailing ones of Excel exports, aren't peptides
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+
+        let field = |column: usize| record.get(column).map_or("", |value| value.as_str());
+
+        let protein = field(layout.protein).to_string();
+        let name = field(layout.peptide).to_string();
+        let charge_mass_ratio = match field(layout.mass_charge_ratio).trim().parse::<f64>() {
+            Ok(ratio) if ratio.is_finite() => ratio,
+            _ => {
+                warnings.push(ParseWarning {
+                    row: line,
+                    column: layout.mass_charge_ratio + 1,
+                    value: field(layout.mass_charge_ratio).to_string(),
+                    problem: ParseProblem::MassChargeRatio,
+                });
+                continue;
+            }
+        };
+        let intensities = layout.samples.iter().map(|&column| {
+            let value = field(column).trim();
+            if value.is_empty() || value == "#N/A" {
+                return None;
+            }
+
+            match value.parse::<f64>() {
+                Ok(intensity) if intensity.is_finite() => Some(intensity),
+                _ => {
+                    warnings.push(ParseWarning {
+                        row: line,
+                        column: column + 1,
+                        value: value.to_string(),
+                        problem: ParseProblem::Intensity,
+                    });
+                    None
+                }
+            }
+        })
+            .collect::<Vec<Option<f64>>>();
+
+        peptides.push(Peptide {
+            name,
+            protein,
+            mass_charge_ratio: charge_mass_ratio,
+            charge: layout.charge_of(&record),
+            intensities,
+        });
+    }
+
+    (peptides, warnings)
+}
+
+fn extract_samples(path: &Path, layout: &Layout) -> Result<Vec<Sample>, PipelineError> {
+    let missing = |label: &str| parse_error(path, None, None, format!("Couldn't find the {label} row"));
+
+    let (line, record) = layout.header_row(DAY_LABEL).ok_or_else(|| missing(DAY_LABEL))?;
+    let days = layout.samples.iter()
+        .map(|&column| {
+            record.get(column).map_or("", |day| day.trim()).parse::<Day>()
+                .map_err(|_| parse_error(path, Some(*line), Some(column + 1), "Failed to parse day"))
+        })
+        .collect::<Result<Vec<Day>, PipelineError>>()?;
+
+    let (_, record) = layout.header_row(MOUSE_LABEL).ok_or_else(|| missing(MOUSE_LABEL))?;
+    let mice = layout.sample_values(record);
+
+    let (_, record) = layout.header_row(ENRICHMENT_LABEL).ok_or_else(|| missing(ENRICHMENT_LABEL))?;
+    let labels = layout.sample_values(record);
+
+    let attributes: Vec<(&String, Vec<String>)> = layout.header_rows.iter()
+        .filter(|(label, _)| ![DAY_LABEL, MOUSE_LABEL, ENRICHMENT_LABEL].iter().any(|known| same_label(label, known)))
+        .map(|(label, (_, record))| (label, layout.sample_values(record)))
+        .collect();
+
+    let samples = layout.samples.iter().enumerate()
+        .map(|(i, &column)| {
+            let heading = layout.headings.get(column).map_or("", |heading| heading.trim());
+            Sample {
+                name: if heading.is_empty() { format!("Column {}", column + 1) } else { heading.to_string() },
+                day: days[i],
+                mouse: mice[i].clone(),
+                label: labels[i].clone(),
+                attributes: attributes.iter()
+                    .map(|(label, values)| (label.to_string(), values[i].clone()))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse_csv(contents: &str) -> Result<(Vec<Sample>, Vec<Peptide>, Vec<ParseWarning>), PipelineError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.csv");
+        fs::write(&path, contents).await.unwrap();
+        parse(&path, None).await
+    }
+
+    const STANDARD: &str = "\
+Day,,,0,14,14
+,,,,,
+Mouse,,,Unlabeled,1,3
+,,,,,
+Body water enrichment,,,Unlabeled,3.994,4.235
+,,,,,
+Protein,Peptide,Product Mz,Unlabeled_1,1,3
+Bsa,LVTDLTK,395.239461,7181468160,#N/A,5885513728
+Bsa,LVTDLTK,395.740957,2938600960,1457995648.5,2508054784
+";
+
+    #[tokio::test]
+    async fn reads_the_standard_layout() {
+        let (samples, peptides, warnings) = parse_csv(STANDARD).await.unwrap();
+
+        let names: Vec<&str> = samples.iter().map(|sample| sample.name.as_str()).collect();
+        assert_eq!(names, ["Unlabeled_1", "1", "3"]);
+        assert_eq!(samples.iter().map(|sample| sample.day).collect::<Vec<_>>(), [0, 14, 14]);
+        assert_eq!(samples[1].mouse, "1");
+        assert_eq!(samples[2].label, "4.235");
+
+        assert_eq!(peptides.len(), 2);
+        assert_eq!(peptides[0].protein, "Bsa");
+        assert_eq!(peptides[0].name, "LVTDLTK");
+        assert_eq!(peptides[0].mass_charge_ratio, 395.239461);
+        assert_eq!(peptides[0].charge, None);
+        assert_eq!(peptides[0].intensities, [Some(7181468160.0), None, Some(5885513728.0)]);
+        assert_eq!(peptides[1].intensities[1], Some(1457995648.5));
+        assert!(warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn finds_reordered_header_rows() {
+        let contents = "\
+Body water enrichment,,,Unlabeled,3.994
+Mouse,,,Unlabeled,1
+Day,,,0,14
+Protein,Peptide,Product Mz,Unlabeled_1,1
+Bsa,LVTDLTK,395.239461,1,2
+";
+        let (samples, peptides, _) = parse_csv(contents).await.unwrap();
+
+        assert_eq!(samples[1].day, 14);
+        assert_eq!(samples[1].mouse, "1");
+        assert_eq!(samples[1].label, "3.994");
+        assert_eq!(peptides.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn keeps_extra_metadata_rows_as_attributes() {
+        let contents = "\
+Experiment,Liver,,,
+Day,,,0,14
+Condition,,,,Treated
+Mouse,,,Unlabeled,1
+Body water enrichment,,,Unlabeled,3.994
+Protein,Peptide,Product Mz,Unlabeled_1,1
+Bsa,LVTDLTK,395.239461,1,2
+";
+        let (samples, _, _) = parse_csv(contents).await.unwrap();
+
+        assert_eq!(samples[0].attribute("condition"), Some(""));
+        assert_eq!(samples[1].attribute("Condition"), Some("Treated"));
+        assert_eq!(samples[1].attribute("Experiment"), Some(""));
+        assert_eq!(samples[1].attribute("Missing"), None);
+    }
+
+    #[tokio::test]
+    async fn skips_annotation_columns_before_the_samples() {
+        let contents = "\
+Day,,,,0,14
+Mouse,,,,Unlabeled,1
+Body water enrichment,,,,Unlabeled,3.994
+Protein,Peptide,Product Mz,Notes,Unlabeled_1,1
+Bsa,LVTDLTK,395.239461,checked,1,2
+";
+        let (samples, peptides, warnings) = parse_csv(contents).await.unwrap();
+
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].name, "Unlabeled_1");
+        assert_eq!(peptides[0].intensities, [Some(1.0), Some(2.0)]);
+        assert!(warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_columns_after_the_samples() {
+        let contents = "\
+Day,,,0,14,
+Mouse,,,Unlabeled,1,
+Body water enrichment,,,Unlabeled,3.994,
+Protein,Peptide,Product Mz,Unlabeled_1,1,Notes
+Bsa,LVTDLTK,395.239461,1,2,checked
+";
+        let (samples, peptides, warnings) = parse_csv(contents).await.unwrap();
+
+        assert_eq!(samples.len(), 2);
+        assert_eq!(peptides[0].intensities, [Some(1.0), Some(2.0)]);
+        assert!(warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_the_charge_column() {
+        let contents = "\
+Day,,,,0,14
+Mouse,,,,Unlabeled,1
+Body water enrichment,,,,Unlabeled,3.994
+Protein,Peptide,Product Mz,Precursor Charge,Unlabeled_1,1
+Bsa,LVTDLTK,395.239461,2,1,2
+Bsa,YLYEIAR,464.7,3+,1,2
+Bsa,LVNELTEFAK,582.3,,1,2
+Bsa,AEFVEVTK,461.7,two,1,2
+";
+        let (samples, peptides, _) = parse_csv(contents).await.unwrap();
+
+        assert_eq!(samples.len(), 2);
+        let charges: Vec<Option<u32>> = peptides.iter().map(|peptide| peptide.charge).collect();
+        assert_eq!(charges, [Some(2), Some(3), None, None]);
+    }
+
+    #[tokio::test]
+    async fn reads_a_charge_column_after_the_samples() {
+        let contents = "\
+Day,,,0,14,
+Mouse,,,Unlabeled,1,
+Body water enrichment,,,Unlabeled,3.994,
+Protein,Peptide,Product Mz,Unlabeled_1,1,Charge
+Bsa,LVTDLTK,395.239461,1,2,2
+";
+        let (samples, peptides, warnings) = parse_csv(contents).await.unwrap();
+
+        assert_eq!(samples.iter().map(|sample| sample.name.as_str()).collect::<Vec<_>>(), ["Unlabeled_1", "1"]);
+        assert_eq!(peptides[0].intensities, [Some(1.0), Some(2.0)]);
+        assert_eq!(peptides[0].charge, Some(2));
+        assert!(warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_unreadable_cells_and_skips_blank_rows() {
+        let contents = STANDARD.to_string() + "\
+Bsa,YLYEIAR,NaN,1,2,3
+Bsa,YLYEIAR,464.7,1,lots,3
+,,,,,
+";
+        let (_, peptides, warnings) = parse_csv(&contents).await.unwrap();
+
+        assert_eq!(peptides.len(), 3);
+        assert_eq!(peptides[2].intensities, [Some(1.0), None, Some(3.0)]);
+
+        let problems: Vec<(usize, usize, ParseProblem)> = warnings.iter()
+            .map(|warning| (warning.row, warning.column, warning.problem))
+            .collect();
+        assert_eq!(problems, [
+            (10, 3, ParseProblem::MassChargeRatio),
+            (11, 5, ParseProblem::Intensity),
+        ]);
+    }
+
+    // Writes CSV text into a sheet from the given first cell, with `#N/A` as a formula error
+    fn write_rows(worksheet: &mut rust_xlsxwriter::Worksheet, first_row: u32, first_column: u16, contents: &str) {
+        for (row, line) in contents.lines().enumerate() {
+            for (column, value) in line.split(',').enumerate() {
+                let (row, column) = (first_row + row as u32, first_column + column as u16);
+                match value {
+                    "" => {}
+                    "#N/A" => {
+                        worksheet.write_formula(row, column, rust_xlsxwriter::Formula::new("=NA()").set_result("#N/A")).unwrap();
+                    }
+                    _ => {
+                        match value.parse::<f64>() {
+                            Ok(number) => worksheet.write_number(row, column, number).unwrap(),
+                            Err(_) => worksheet.write_string(row, column, value).unwrap(),
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    // A Notes sheet followed by a Data sheet holding the standard layout two rows down
+    fn write_workbook(path: &Path) {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let notes = workbook.add_worksheet().set_name("Notes").unwrap();
+        notes.write_string(0, 0, "Liver, week 2").unwrap();
+        let data = workbook.add_worksheet().set_name("Data").unwrap();
+        write_rows(data, 2, 0, STANDARD);
+        workbook.save(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_a_sheet_by_name_or_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.xlsx");
+        write_workbook(&path);
+
+        for sheet in [Sheet::Name("Data".to_string()), Sheet::Index(1)] {
+            let (samples, peptides, warnings) = parse(&path, Some(&sheet)).await.unwrap();
+
+            assert_eq!(samples.iter().map(|sample| sample.name.as_str()).collect::<Vec<_>>(), ["Unlabeled_1", "1", "3"]);
+            assert_eq!(samples.iter().map(|sample| sample.day).collect::<Vec<_>>(), [0, 14, 14]);
+            assert_eq!(peptides[0].intensities, [Some(7181468160.0), None, Some(5885513728.0)]);
+            assert_eq!(peptides[1].intensities[1], Some(1457995648.5));
+            assert!(warnings.is_empty());
+        }
+
+        // The first sheet is read by default
+        match parse(&path, None).await {
+            Err(err) => assert_eq!(err.to_string(), "Couldn't find the Protein header row"),
+            Ok(_) => panic!("parsed the Notes sheet"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_sheets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.xlsx");
+        write_workbook(&path);
+
+        let message = |sheet: Sheet| {
+            let path = path.clone();
+            async move { parse(&path, Some(&sheet)).await.err().map(|err| err.to_string()) }
+        };
+        assert_eq!(message(Sheet::Name("Results".to_string())).await.as_deref(), Some("Sheet \"Results\" doesn't exist in the workbook"));
+        assert_eq!(message(Sheet::Index(2)).await.as_deref(), Some("The workbook doesn't have a sheet at position 2"));
+    }
+
+    #[tokio::test]
+    async fn lines_up_workbook_rows_with_the_sheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.xlsx");
+
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        write_rows(worksheet, 3, 2, "Bsa,\n,#N/A");
+        worksheet.write_formula(5, 2, rust_xlsxwriter::Formula::new("=1/0").set_result("#DIV/0!")).unwrap();
+        workbook.save(&path).unwrap();
+
+        // Row numbers are the ones of the sheet and cells are padded up to column C
+        assert_eq!(read_rows(&path, None).await.unwrap(), [
+            (4, vec![String::new(), String::new(), "Bsa".to_string(), String::new()]),
+            (5, vec![String::new(), String::new(), String::new(), "#N/A".to_string()]),
+            (6, vec![String::new(), String::new(), "#N/A".to_string(), String::new()]),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn fails_without_the_header_rows() {
+        let without_protein = "Day,,,0,14\nMouse,,,Unlabeled,1\n";
+        assert!(matches!(parse_csv(without_protein).await, Err(PipelineError::Parse { .. })));
+
+        let without_day = "Mouse,,,Unlabeled,1\nProtein,Peptide,Product Mz,A,B\nBsa,LVTDLTK,395.2,1,2\n";
+        match parse_csv(without_day).await {
+            Err(err) => assert_eq!(err.to_string(), "Couldn't find the Day row"),
+            Ok(_) => panic!("parsed a file without a Day row"),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::thread::available_parallelism;
+use tokio::sync::Semaphore;
+
+/// Limits how many engine runs happen at once.
+///
+/// Clones share the same permits, so one pool handed to every input file bounds the engine
+/// runs across all of them.
+#[derive(Debug, Clone)]
+pub struct WorkerPool {
+    permits: Arc<Semaphore>,
+    workers: usize,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+
+        Self {
+            permits: Arc::new(Semaphore::new(workers)),
+            workers,
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Waits for a free worker, then drives `task` to completion while holding it.
+    pub async fn run<F: Future>(&self, task: F) -> F::Output {
+        let _permit = self.permits.acquire().await.expect("worker pool semaphore is never closed");
+        task.await
+    }
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self::new(available_parallelism().map(NonZeroUsize::get).unwrap_or(1))
+    }
+}
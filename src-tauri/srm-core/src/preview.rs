@@ -0,0 +1,82 @@
+use std::path::Path;
+use serde::Serialize;
+use crate::error::PipelineError;
+use crate::grouper::{assign_na_groups, explain_group_by_peptides, GroupingStrategy, SplitDecision};
+use crate::parser::{parse, Sheet};
+
+/// What grouping a file with a tolerance multiplier gives, before running the engine.
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupingPreview {
+    /// Sample names, in the order of the NA columns
+    pub samples: Vec<String>,
+    pub peptide_groups: Vec<PeptideGroupPreview>,
+    pub na_groups: Vec<NAGroupPreview>,
+}
+
+/// Transitions of one peptide grouped together, usually one charge state.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeptideGroupPreview {
+    pub protein: String,
+    pub peptide: String,
+    pub transitions: Vec<TransitionPreview>,
+    /// Whether a transition of the group is missing the intensity of each sample
+    pub na_columns: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitionPreview {
+    pub mass_charge_ratio: f64,
+    /// `None` for the first transition of a peptide
+    pub decision: Option<SplitDecision>,
+}
+
+/// Peptide groups sharing an NA column pattern, which become one dataset.
+#[derive(Debug, Clone, Serialize)]
+pub struct NAGroupPreview {
+    pub na_columns: Vec<bool>,
+    /// Indices into [`GroupingPreview::peptide_groups`]
+    pub peptide_groups: Vec<usize>,
+}
+
+/// Parses `spreadsheet` and groups its peptides like [`crate::process_file`] does, keeping the
+/// threshold behind every split so the effect of `strategy` and `tolerance_multiplier` can be
+/// shown.
+pub async fn explain_grouping(
+    spreadsheet: &Path,
+    sheet: Option<&Sheet>,
+    strategy: GroupingStrategy,
+    tolerance_multiplier: f64,
+) -> Result<GroupingPreview, PipelineError> {
+    let (samples, peptides, _) = parse(spreadsheet, sheet).await?;
+
+    let (groups, decisions): (Vec<_>, Vec<_>) = explain_group_by_peptides(strategy, tolerance_multiplier, peptides)
+        .into_iter()
+        .unzip();
+
+    let na_groups = assign_na_groups(&groups)
+        .into_iter()
+        .map(|(na_columns, peptide_groups)| NAGroupPreview { na_columns, peptide_groups })
+        .collect();
+
+    let peptide_groups = groups.into_iter()
+        .zip(decisions)
+        .map(|(group, decisions)| PeptideGroupPreview {
+            protein: group.peptides.first().map_or_else(String::new, |peptide| peptide.protein.clone()),
+            peptide: group.peptides.first().map_or_else(String::new, |peptide| peptide.name.clone()),
+            transitions: group.peptides.iter()
+                .zip(decisions)
+                .map(|(peptide, decision)| TransitionPreview {
+                    mass_charge_ratio: peptide.mass_charge_ratio,
+                    decision,
+                })
+                .collect(),
+            na_columns: group.na_columns,
+        })
+        .collect();
+
+    Ok(GroupingPreview {
+        samples: samples.into_iter().map(|sample| sample.name).collect(),
+        peptide_groups,
+        na_groups,
+    })
+}
@@ -0,0 +1,338 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::create_dir;
+use crate::aggregator::{aggregate, ResultOrder, sort_results, summarize_proteins};
+use crate::analyzer::{analyze_all, DatasetFailure};
+use crate::comparator::{compare, ComparisonMethod};
+use crate::engine::DynEngine;
+use crate::error::PipelineError;
+use crate::grouper::{group_by_na_columns, group_by_strategy, GroupingStrategy};
+use crate::parser::{InputFile, parse, ParseWarning};
+use crate::pool::WorkerPool;
+use crate::progress::{ProgressCallback, ProgressUpdate};
+use crate::serializer::{
+    DerivedMetric,
+    OutputFormat,
+    prepare_datasets,
+    serialize_calculations,
+    serialize_comparisons,
+    serialize_protein_summaries,
+    serialize_workbook,
+};
+
+/// Settings shared by every file of a run.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    pub should_remove_na_calculations: bool,
+    pub tolerance_multiplier: f64,
+    /// How the transitions of a peptide are split into charge states
+    pub grouping_strategy: GroupingStrategy,
+    /// How long one engine run may take before its dataset is marked as failed.
+    pub engine_timeout: Option<Duration>,
+    pub output_format: OutputFormat,
+    /// Header row, such as `Condition`, whose values split the samples into separately fitted
+    /// datasets. Results then get a Condition column.
+    pub condition_row: Option<String>,
+    /// Test used to compare the conditions of a split run, see [`crate::comparator::compare`]
+    pub comparison: Option<ComparisonMethod>,
+    /// Columns computed from the rate constants, such as the half-life
+    pub derived_metrics: Vec<DerivedMetric>,
+    pub result_order: ResultOrder,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            should_remove_na_calculations: true,
+            tolerance_multiplier: 2.0,
+            grouping_strategy: GroupingStrategy::Heuristic,
+            engine_timeout: None,
+            output_format: OutputFormat::Csv,
+            condition_row: None,
+            comparison: None,
+            derived_metrics: vec![],
+            result_order: ResultOrder::Input,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessSummary {
+    pub output: PathBuf,
+    pub calculations: usize,
+    pub failures: Vec<DatasetFailure>,
+    /// Cells that couldn't be read: intensities treated as missing and rows skipped for their m/z.
+    pub warnings: Vec<ParseWarning>,
+    /// `{name}.Comparison.csv`, when conditions were compared and the results are written as CSV
+    pub comparison_output: Option<PathBuf>,
+}
+
+impl ProcessSummary {
+    pub fn failed_peptides(&self) -> usize {
+        self.failures.iter().map(|failure| failure.peptides.len()).sum()
+    }
+}
+
+/// Runs the whole pipeline for one input file and writes `{name}.RateConst.csv` (or `.xlsx`,
+/// depending on [`ProcessOptions::output_format`]) next to it, with the protein roll-up in
+/// `{name}.Proteins.csv`. Comparisons between conditions go to `{name}.Comparison.csv`. Workbooks
+/// hold the roll-up and the comparisons as extra sheets instead.
+///
+/// Datasets the engine fails on, or takes longer than the engine timeout for, are reported in
+/// [`ProcessSummary::failures`] and as error rows in the output while the other datasets carry on.
+///
+/// Dropping the returned future cancels processing: running engines are killed and the
+/// temporary data directory is removed.
+pub async fn process_file(
+    engine: &dyn DynEngine,
+    pool: &WorkerPool,
+    options: &ProcessOptions,
+    input_file_path: InputFile,
+    progress_callback: ProgressCallback,
+) -> Result<ProcessSummary, PipelineError> {
+    progress_callback(ProgressUpdate::Set {
+        iterations: 0,
+        total_iterations: Some(100),
+    });
+
+    let temp_dir = tempfile::tempdir().map_err(|e| PipelineError::Serialization {
+        path: std::env::temp_dir(),
+        message: format!("Couldn't create a temporary directory: {e}"),
+    })?;
+    let data_dir = temp_dir.path().join("data");
+
+    create_dir(&data_dir).await.map_err(|e| PipelineError::Serialization {
+        path: data_dir.clone(),
+        message: format!("Couldn't create the data directory: {e}"),
+    })?;
+
+    let sheet = input_file_path.sheet.clone();
+    let input_file_path = Path::new(&input_file_path.path);
+
+    let (
+        samples,
+        peptides,
+        warnings
+    ) = parse(input_file_path, sheet.as_ref()).await?;
+
+    if let Some(condition_row) = &options.condition_row {
+        if !samples.iter().any(|sample| sample.attribute(condition_row).is_some()) {
+            return Err(PipelineError::InvalidOption {
+                name: "split by".to_string(),
+                message: format!("Couldn't find the {condition_row} row to split the samples by"),
+            });
+        }
+    }
+
+    if peptides.is_empty() {
+        return Err(PipelineError::Grouping {
+            path: input_file_path.to_path_buf(),
+            message: "The file doesn't contain any peptides".to_string(),
+        });
+    }
+
+    let groups = group_by_na_columns(group_by_strategy(
+        options.grouping_strategy,
+        options.tolerance_multiplier,
+        peptides.clone(),
+    ));
+    let datasets = prepare_datasets(
+        options.should_remove_na_calculations,
+        options.condition_row.as_deref(),
+        samples,
+        groups,
+    );
+
+    progress_callback(ProgressUpdate::Set {
+        iterations: 0,
+        total_iterations: Some(datasets.len() * 2 + datasets.len() / 10),
+    });
+
+    let outcomes = analyze_all(engine, pool, options.engine_timeout, &data_dir, &datasets, &progress_callback).await;
+    let (mut calculations, mut failures) = aggregate(outcomes);
+    sort_results(options.result_order, &peptides, &mut calculations, &mut failures);
+    let proteins = summarize_proteins(&calculations);
+    let comparisons = options.comparison
+        .map(|method| compare(&calculations, method))
+        .unwrap_or_default();
+
+    let input_file_name = input_file_path
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+
+    let file_path = input_file_path.parent().unwrap().join(format!(
+        "{}.RateConst.{}",
+        input_file_name,
+        options.output_format.extension(),
+    ));
+
+    let mut comparison_output = None;
+    if options.output_format == OutputFormat::Csv {
+        let proteins_path = input_file_path.parent().unwrap().join(format!("{input_file_name}.Proteins.csv"));
+        serialize_protein_summaries(&proteins_path, &proteins).map_err(|e| PipelineError::Serialization {
+            path: proteins_path,
+            message: format!("Couldn't write the protein summary: {e}"),
+        })?;
+    }
+
+    let written = match options.output_format {
+        OutputFormat::Csv if !comparisons.is_empty() => {
+            let comparison_path = input_file_path.parent().unwrap().join(format!("{input_file_name}.Comparison.csv"));
+            serialize_comparisons(&comparison_path, &comparisons).map_err(|e| PipelineError::Serialization {
+                path: comparison_path.clone(),
+                message: format!("Couldn't write the comparisons: {e}"),
+            })?;
+            comparison_output = Some(comparison_path);
+
+            serialize_calculations(&file_path, &calculations, &failures, &options.derived_metrics)
+        }
+        OutputFormat::Csv => serialize_calculations(&file_path, &calculations, &failures, &options.derived_metrics),
+        OutputFormat::Xlsx => {
+            let parameters = [
+                ("Input file", input_file_path.display().to_string()),
+                ("Engine", engine.name()),
+                ("Grouping", match options.grouping_strategy {
+                    GroupingStrategy::Heuristic => "Heuristic".to_string(),
+                    GroupingStrategy::Charge => "Charge column".to_string(),
+                    GroupingStrategy::Ppm { tolerance } => format!("Isotope spacing ({tolerance} ppm)"),
+                }),
+                ("Tolerance multiplier", options.tolerance_multiplier.to_string()),
+                ("Remove NA calculations", options.should_remove_na_calculations.to_string()),
+                ("Engine timeout (seconds)", options.engine_timeout
+                    .map(|timeout| timeout.as_secs_f64().to_string())
+                    .unwrap_or_default()),
+                ("Split by", options.condition_row.clone().unwrap_or_default()),
+                ("Comparison", match options.comparison {
+                    Some(ComparisonMethod::Welch) => "Welch t-test".to_string(),
+                    Some(ComparisonMethod::Bootstrap { iterations }) => format!("Bootstrap ({iterations} iterations)"),
+                    None => String::new(),
+                }),
+                ("Result order", match options.result_order {
+                    ResultOrder::Input => "Input order".to_string(),
+                    ResultOrder::Name => "By name".to_string(),
+                }),
+                ("Derived columns", options.derived_metrics.iter()
+                    .map(|metric| match metric {
+                        DerivedMetric::HalfLife => "Half-life",
+                        DerivedMetric::Turnover => "Turnover",
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")),
+            ];
+            serialize_workbook(
+                &file_path,
+                &calculations,
+                &failures,
+                &options.derived_metrics,
+                &proteins,
+                &comparisons,
+                &parameters,
+            )
+        }
+    };
+    written.map_err(|e| PipelineError::Serialization {
+        path: file_path.clone(),
+        message: format!("Couldn't write the results: {e}"),
+    })?;
+
+    progress_callback(ProgressUpdate::Set {
+        iterations: 100,
+        total_iterations: Some(100),
+    });
+
+    temp_dir.close().map_err(|e| PipelineError::Serialization {
+        path: data_dir,
+        message: format!("Couldn't remove the temporary directory: {e}"),
+    })?;
+
+    Ok(ProcessSummary {
+        output: file_path,
+        calculations: calculations.len(),
+        failures,
+        warnings,
+        comparison_output,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use calamine::Reader;
+    use crate::{builtin_engine, EngineType, Runner};
+
+    use super::*;
+
+    const INPUT: &str = "\
+Day,,,0,14,14
+Condition,,,,Control,Treated
+Mouse,,,Unlabeled,1,2
+Body water enrichment,,,Unlabeled,3.994,4.235
+Protein,Peptide,Product Mz,Unlabeled_1,1,2
+Bsa,LVTDLTK,395.239461,7181468160,4457995648,5885513728
+Bsa,LVTDLTK,395.740957,2938600960,1457995648,2508054784
+";
+
+    async fn process(dir: &Path, contents: &str, options: ProcessOptions) -> Result<ProcessSummary, PipelineError> {
+        let path = dir.join("input.csv");
+        tokio::fs::write(&path, contents).await.unwrap();
+
+        let engine = builtin_engine(EngineType::Native, dir, Runner::direct());
+        process_file(
+            engine.as_ref(),
+            &WorkerPool::new(1),
+            &options,
+            InputFile { uuid: String::new(), path, sheet: None },
+            Box::new(|_| {}),
+        ).await
+    }
+
+    async fn process_split_by(condition_row: &str) -> Result<ProcessSummary, PipelineError> {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ProcessOptions { condition_row: Some(condition_row.to_string()), ..ProcessOptions::default() };
+        process(dir.path(), INPUT, options).await
+    }
+
+    #[tokio::test]
+    async fn splits_by_a_header_row() {
+        let summary = process_split_by("condition").await.unwrap();
+        assert!(summary.failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_an_unknown_split_row() {
+        match process_split_by("Condtion").await {
+            Err(PipelineError::InvalidOption { message, .. }) => {
+                assert_eq!(message, "Couldn't find the Condtion row to split the samples by");
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn describes_the_run_in_the_workbook() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ProcessOptions {
+            output_format: OutputFormat::Xlsx,
+            condition_row: Some("Condition".to_string()),
+            derived_metrics: vec![DerivedMetric::HalfLife, DerivedMetric::Turnover],
+            result_order: ResultOrder::Name,
+            ..ProcessOptions::default()
+        };
+        let summary = process(dir.path(), &INPUT.replace("4457995648", "#N/A"), options).await.unwrap();
+
+        let mut workbook = calamine::open_workbook_auto(&summary.output).unwrap();
+        let rows = |range: calamine::Range<calamine::Data>| -> Vec<Vec<String>> {
+            range.rows().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+        };
+
+        let omitted = rows(workbook.worksheet_range("Omitted Samples").unwrap());
+        assert_eq!(omitted[0], ["Protein", "Peptide", "Condition", "Sample", "Day", "Mouse", "Body water enrichment", "Condition"]);
+        assert_eq!(omitted[1], ["Bsa", "LVTDLTK", "Control", "1", "14", "1", "3.994", "Control"]);
+
+        let parameters = rows(workbook.worksheet_range("Parameters").unwrap());
+        let parameter = |name: &str| parameters.iter().find(|row| row[0] == name).map(|row| row[1].clone());
+        assert_eq!(parameter("Result order").as_deref(), Some("By name"));
+        assert_eq!(parameter("Derived columns").as_deref(), Some("Half-life, Turnover"));
+    }
+}
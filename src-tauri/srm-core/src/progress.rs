@@ -0,0 +1,11 @@
+pub enum ProgressUpdate {
+    Set {
+        iterations: usize,
+        total_iterations: Option<usize>,
+    },
+    Increment {
+        iterations: usize,
+    },
+}
+
+pub type ProgressCallback = Box<dyn Fn(ProgressUpdate) + Send + Sync>;
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use tokio::process::{Child, Command};
+use crate::error::PipelineError;
+
+/// How an engine executable gets launched.
+///
+/// When `wrapper` is set the engine is started as `wrapper[0] wrapper[1..] program args`, which
+/// is how SRM_Rate.exe runs under wine or inside a container. `env` is added to the
+/// environment of the launched process.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Runner {
+    #[serde(default)]
+    pub wrapper: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+impl Runner {
+    pub fn direct() -> Self {
+        Self::default()
+    }
+
+    pub fn wrapped(wrapper: Vec<String>) -> Self {
+        Self {
+            wrapper,
+            env: HashMap::new(),
+        }
+    }
+
+    /// Runs the engine directly on Windows and through wine everywhere else.
+    pub fn for_platform() -> Self {
+        if cfg!(windows) {
+            Self::direct()
+        } else {
+            let mut runner = Self::wrapped(vec!["wine".to_string()]);
+            runner.env.insert("WINEDEBUG".to_string(), "-all".to_string());
+            runner
+        }
+    }
+
+    pub fn with_env(mut self, key: String, value: String) -> Self {
+        self.env.insert(key, value);
+        self
+    }
+
+    pub fn command(&self, program: &Path) -> Command {
+        let mut command = match self.wrapper.split_first() {
+            Some((launcher, launcher_args)) => {
+                let mut command = Command::new(launcher);
+                command.args(launcher_args).arg(program);
+                command
+            }
+            None => Command::new(program),
+        };
+
+        command.envs(&self.env);
+        command
+    }
+
+    /// Makes sure the wrapper can be found before any dataset is handed to the engine.
+    pub fn check(&self) -> Result<(), PipelineError> {
+        match self.wrapper.first() {
+            Some(launcher) if find_executable(launcher).is_none() => Err(PipelineError::EngineLaunch {
+                program: launcher.clone(),
+                message: format!(
+                    "The engine wrapper `{launcher}` couldn't be found. Install it or configure a different runner."
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let extensions: Vec<String> = if cfg!(windows) {
+        env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .map(|extension| extension.to_string())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths).find_map(|dir| {
+            let candidate = dir.join(name);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+
+            extensions.iter()
+                .map(|extension| dir.join(format!("{name}{extension}")))
+                .find(|candidate| candidate.is_file())
+        })
+    })
+}
+
+/// Every process started for one engine run, such as the program a wrapper like wine or a
+/// container runtime launches. Dropping it kills them all, which `kill_on_drop` alone doesn't do
+/// beyond the direct child.
+pub struct ProcessGroup {
+    #[cfg(unix)]
+    id: libc::pid_t,
+    #[cfg(windows)]
+    job: windows_sys::Win32::Foundation::HANDLE,
+}
+
+// The job handle is only used to close it
+#[cfg(windows)]
+unsafe impl Send for ProcessGroup {}
+
+impl ProcessGroup {
+    /// Starts `command` in a process group of its own.
+    #[cfg(unix)]
+    pub fn spawn(command: &mut Command) -> std::io::Result<(Child, Self)> {
+        command.process_group(0);
+        let child = command.spawn()?;
+        let id = child.id().map_or(0, |id| id as libc::pid_t);
+        Ok((child, Self { id }))
+    }
+
+    /// Starts `command` inside a job object that kills its processes when closed.
+    #[cfg(windows)]
+    pub fn spawn(command: &mut Command) -> std::io::Result<(Child, Self)> {
+        use std::mem::{size_of, zeroed};
+        use windows_sys::Win32::System::JobObjects::{
+            AssignProcessToJobObject,
+            CreateJobObjectW,
+            JobObjectExtendedLimitInformation,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+            JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+            SetInformationJobObject,
+        };
+
+        let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if job.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+        let group = Self { job };
+
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let configured = unsafe {
+            SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &limits as *const _ as *const std::ffi::c_void,
+                size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if configured == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let child = command.spawn()?;
+        if let Some(handle) = child.raw_handle() {
+            if unsafe { AssignProcessToJobObject(job, handle as _) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        Ok((child, group))
+    }
+}
+
+impl Drop for ProcessGroup {
+    #[cfg(unix)]
+    fn drop(&mut self) {
+        if self.id > 0 {
+            // Fails harmlessly when every process of the group has already exited
+            unsafe { libc::kill(-self.id, libc::SIGKILL) };
+        }
+    }
+
+    #[cfg(windows)]
+    fn drop(&mut self) {
+        unsafe { windows_sys::Win32::Foundation::CloseHandle(self.job) };
+    }
+}
@@ -0,0 +1,757 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use csv::Writer;
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use serde::Deserialize;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use crate::aggregator::{Calculation, ENGINE_HEADERS, ProteinSummary};
+use crate::analyzer::DatasetFailure;
+use crate::comparator::Comparison;
+use crate::grouper::NAGroup;
+use crate::parser::{Day, Label, Mouse, Peptide, Sample};
+
+/// Column computed from the rate constant and written after the engine columns, along with its
+/// values at the Two_SD bounds.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DerivedMetric {
+    /// Days for half of the protein to be replaced
+    HalfLife,
+    /// Fractional synthesis rate, the rate constant in percent per day
+    Turnover,
+}
+
+impl DerivedMetric {
+    fn headers(&self) -> [&'static str; 3] {
+        match self {
+            DerivedMetric::HalfLife => ["Half-life (days)", "Half-life lower", "Half-life upper"],
+            DerivedMetric::Turnover => ["Turnover (%/day)", "Turnover lower", "Turnover upper"],
+        }
+    }
+
+    fn values(&self, calculation: &Calculation) -> [Option<f64>; 3] {
+        match self {
+            DerivedMetric::HalfLife => {
+                let (lower, upper) = calculation.half_life_bounds();
+                [calculation.half_life(), lower, upper]
+            }
+            DerivedMetric::Turnover => [
+                calculation.mean.value,
+                calculation.two_sd_minus.value,
+                calculation.two_sd_plus.value,
+            ].map(|rate| rate.map(|rate| rate * 100.0)),
+        }
+    }
+}
+
+/// File format the rate constants are written in.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub samples: Vec<Sample>,
+    pub peptides: Vec<Peptide>,
+    /// Samples left out because a peptide of the dataset has no intensity for them
+    pub samples_omitted: Vec<Sample>,
+    /// Condition the samples were selected for when the run is split by condition
+    pub condition: Option<String>,
+}
+
+impl Dataset {
+    pub fn days(&self) -> Vec<Day> {
+        self.samples.iter().map(|sample| sample.day).collect()
+    }
+
+    pub fn mice(&self) -> Vec<Mouse> {
+        self.samples.iter().map(|sample| sample.mouse.clone()).collect()
+    }
+
+    pub fn labels(&self) -> Vec<Label> {
+        self.samples.iter().map(|sample| sample.label.clone()).collect()
+    }
+}
+
+/// Builds the datasets the engine runs on. With a `condition_row`, each group is split into one
+/// dataset per value of that header row, see [`split_by_condition`].
+pub fn prepare_datasets(
+    should_remove_na_calculations: bool,
+    condition_row: Option<&str>,
+    samples: Vec<Sample>,
+    groups: Vec<NAGroup>,
+) -> Vec<Dataset> {
+    let conditions = match condition_row {
+        Some(condition_row) => split_by_condition(condition_row, &samples),
+        None => vec![(None, (0..samples.len()).collect())],
+    };
+
+    let mut datasets = vec![];
+    for group in groups {
+        for (condition, columns) in &conditions {
+            let samples: Vec<Sample> = columns.iter().map(|&i| samples[i].clone()).collect();
+            let group = NAGroup {
+                peptides: group.peptides.iter().map(|peptide| Peptide {
+                    intensities: columns.iter().map(|&i| peptide.intensities.get(i).copied().flatten()).collect(),
+                    ..peptide.clone()
+                }).collect(),
+                na_columns: columns.iter().map(|&i| group.na_columns.get(i).copied().unwrap_or(false)).collect(),
+            };
+
+            let mut dataset = prepare_dataset(should_remove_na_calculations, &samples, group);
+            dataset.condition = condition.clone();
+            datasets.push(dataset);
+        }
+    }
+
+    datasets
+}
+
+/// Sample columns of each value of the `condition_row` header row, in input order. Samples with
+/// no value, such as shared unlabeled baselines, belong to every condition.
+pub fn split_by_condition(condition_row: &str, samples: &[Sample]) -> Vec<(Option<String>, Vec<usize>)> {
+    let condition = |sample: &Sample| sample.attribute(condition_row)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string);
+
+    let mut conditions: Vec<String> = vec![];
+    for value in samples.iter().filter_map(condition) {
+        if !conditions.contains(&value) {
+            conditions.push(value);
+        }
+    }
+
+    if conditions.is_empty() {
+        return vec![(None, (0..samples.len()).collect())];
+    }
+
+    conditions.into_iter()
+        .map(|value| {
+            let columns = samples.iter().enumerate()
+                .filter(|(_, sample)| condition(sample).is_none_or(|condition| condition == value))
+                .map(|(i, _)| i)
+                .collect();
+            (Some(value), columns)
+        })
+        .collect()
+}
+
+pub fn prepare_dataset(
+    should_remove_na_calculations: bool,
+    samples: &[Sample],
+    group: NAGroup,
+) -> Dataset {
+    let (
+        samples,
+        peptides,
+        samples_omitted
+    ) = if should_remove_na_calculations {
+        prepare_peptides(samples, group)
+    } else {
+        (
+            samples.to_vec(),
+            group.peptides,
+            vec![]
+        )
+    };
+
+    Dataset {
+        samples,
+        peptides,
+        samples_omitted,
+        condition: None,
+    }
+}
+
+// Writes the spreadsheet and heavy water file SRM_Rate.exe reads for a dataset
+pub async fn serialize_dataset(path: &Path, dataset: &Dataset) -> anyhow::Result<(PathBuf, PathBuf)> {
+    let (days, labels) = (dataset.days(), dataset.labels());
+    let spreadsheet = serialize_peptides(path, &days, &dataset.mice(), &labels, &dataset.peptides)?;
+    let heavy_water = serialize_heavy_water_file(path, &days, &labels).await?;
+
+    Ok((spreadsheet, heavy_water))
+}
+
+async fn serialize_heavy_water_file(path: &Path, days: &[Day], labels: &[Label]) -> anyhow::Result<PathBuf> {
+    // Ensure the input vectors are of the same length
+    if days.len() != labels.len() {
+        return Err(anyhow!("Days and labels vectors must have the same length."));
+    }
+
+    // Generate a unique file name
+    let file_name = format!("heavy_water_{}.txt", uuid::Uuid::new_v4());
+    let file_path = path.join(file_name);
+
+    // Create and open the file
+    let mut file = File::create(&file_path).await?;
+
+    // Write the header
+    file.write_all(b"Experiment, Labeling\n").await?;
+
+    // Write each day and label to the file
+    for (day, label) in days.iter().zip(labels.iter()) {
+        // Check if the label is numeric and replace non-numeric labels with "0"
+        let numeric_label = if label.parse::<f64>().is_ok() {
+            label
+        } else {
+            "0"
+        };
+        file.write_all(&format!("{}, {}\n", day, numeric_label).into_bytes()).await?;
+    }
+
+    Ok(file_path)
+}
+
+fn serialize_peptides(
+    path: &Path,
+    days: &[Day],
+    mice: &[Mouse],
+    labels: &[Label],
+    peptides: &[Peptide]
+) -> anyhow::Result<PathBuf> {
+    let file_path = path.join(format!("peptides_{}.csv", uuid::Uuid::new_v4()));
+    let mut wtr = Writer::from_path(file_path.clone())?;
+
+    let days_str = [
+        vec!["Day".to_string(), "".to_string(), "".to_string()],
+        days.iter().map(|day| day.to_string()).collect()
+    ].concat();
+
+    let mice_str = [
+        vec!["Mouse".to_string(), "".to_string(), "".to_string()],
+        mice.iter().map(|mouse| mouse.to_string()).collect()
+    ].concat();
+
+    let labels_str = [
+        vec!["Body water enrichment".to_string(), "".to_string(), "".to_string()],
+        labels.iter().map(|label| label.to_string()).collect()
+    ].concat();
+
+    let headers_str = [
+        vec!["Protein".to_string(), "Peptide".to_string(), "Product Mz".to_string()],
+        days.iter().enumerate().map(|(i, _)| i.to_string()).collect()
+    ].concat();
+
+    wtr.write_record(&days_str)?;
+    wtr.write_record(vec![""; headers_str.len()])?;
+    wtr.write_record(&mice_str)?;
+    wtr.write_record(vec![""; headers_str.len()])?;
+    wtr.write_record(&labels_str)?;
+    wtr.write_record(vec![""; headers_str.len()])?;
+    wtr.write_record(&headers_str)?;
+
+    for peptide in peptides {
+        let intensities = peptide
+            .intensities
+            .iter()
+            .map(|i| {
+                match i {
+                    None => "#N/A".to_string(),
+                    Some(i) => i.to_string(),
+                }
+            }).collect();
+
+        let record = [
+            vec![peptide.protein.clone(), peptide.name.clone(), peptide.mass_charge_ratio.to_string()],
+            intensities
+        ].concat();
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+
+    Ok(file_path)
+}
+
+fn prepare_peptides(
+    samples: &[Sample],
+    group: NAGroup,
+) -> (Vec<Sample>, Vec<Peptide>, Vec<Sample>) {
+    // Determine which columns are NA across all groups in the NAGroup
+    let mut na_columns = HashSet::new();
+    for (i, &is_na) in group.na_columns.iter().enumerate() {
+        if is_na {
+            na_columns.insert(i);
+        }
+    }
+
+    // Split the samples, keeping their metadata, and filter the NA columns out of the intensities
+    let (omitted, kept): (Vec<_>, Vec<_>) = samples.iter().enumerate()
+        .partition(|(i, _)| na_columns.contains(i));
+
+    let filtered_peptides = group.peptides.iter().map(|peptide| {
+        let filtered_intensities = peptide.intensities.iter().enumerate()
+            .filter(|(i, _)| !na_columns.contains(i))
+            .map(|(_, &intensity)| intensity)
+            .collect();
+
+        Peptide {
+            name: peptide.name.clone(),
+            protein: peptide.protein.clone(),
+            mass_charge_ratio: peptide.mass_charge_ratio,
+            charge: peptide.charge,
+            intensities: filtered_intensities,
+        }
+    }).collect();
+
+    (
+        kept.into_iter().map(|(_, sample)| sample.clone()).collect(),
+        filtered_peptides,
+        omitted.into_iter().map(|(_, sample)| sample.clone()).collect(),
+    )
+}
+
+const CALCULATION_HEADERS: [&str; 13] = [
+    ENGINE_HEADERS[0],
+    ENGINE_HEADERS[1],
+    ENGINE_HEADERS[2],
+    ENGINE_HEADERS[3],
+    ENGINE_HEADERS[4],
+    ENGINE_HEADERS[5],
+    ENGINE_HEADERS[6],
+    ENGINE_HEADERS[7],
+    ENGINE_HEADERS[8],
+    ENGINE_HEADERS[9],
+    ENGINE_HEADERS[10],
+    ENGINE_HEADERS[11],
+    "",
+];
+
+fn describe_samples_omitted(samples_omitted: &[Sample]) -> String {
+    if samples_omitted.is_empty() {
+        "".to_string()
+    } else {
+        let names: Vec<&str> = samples_omitted.iter().map(|sample| sample.name.as_str()).collect();
+        format!(
+            "{} sample{} omitted ({})",
+            samples_omitted.len(),
+            if samples_omitted.len() > 1 { "s" } else { "" },
+            names.join(", "),
+        )
+    }
+}
+
+// The Condition column is only written for runs split by condition
+fn has_conditions(calculations: &[Calculation], failures: &[DatasetFailure]) -> bool {
+    calculations.iter().any(|calculation| calculation.condition.is_some())
+        || failures.iter().any(|failure| failure.condition.is_some())
+}
+
+fn calculation_headers(with_condition: bool, derived: &[DerivedMetric]) -> Vec<&'static str> {
+    let mut headers = CALCULATION_HEADERS[..12].to_vec();
+    if with_condition {
+        headers.insert(2, "Condition");
+    }
+    headers.extend(derived.iter().flat_map(|metric| metric.headers()));
+    headers.push(CALCULATION_HEADERS[12]);
+    headers
+}
+
+// Derived values that can't be computed, such as for a `-nan(ind)` rate constant, are left empty
+fn derived_values(calculation: &Calculation, derived: &[DerivedMetric]) -> Vec<String> {
+    derived.iter()
+        .flat_map(|metric| metric.values(calculation))
+        .map(|value| match value {
+            Some(value) if value.is_finite() => {
+                format!("{:.6}", value).trim_end_matches('0').trim_end_matches('.').to_string()
+            }
+            _ => String::new(),
+        })
+        .collect()
+}
+
+// Numbers are written with the text the engine wrote them with
+fn calculation_values(calculation: &Calculation) -> [&str; 12] {
+    [
+        &calculation.protein,
+        &calculation.peptide,
+        &calculation.neh.text,
+        &calculation.charge.text,
+        &calculation.mean.text,
+        &calculation.n_ret_1.text,
+        &calculation.mpe_0.text,
+        &calculation.mpe_1.text,
+        &calculation.two_sd_minus.text,
+        &calculation.n_ret_2.text,
+        &calculation.two_sd_plus.text,
+        &calculation.n_ret_3.text,
+    ]
+}
+
+/// Writes the rate constants as CSV, with the `derived` columns after the engine columns.
+pub fn serialize_calculations(
+    path: &Path,
+    calculations: &[Calculation],
+    failures: &[DatasetFailure],
+    derived: &[DerivedMetric],
+) -> anyhow::Result<()> {
+    let with_condition = has_conditions(calculations, failures);
+    let headers = calculation_headers(with_condition, derived);
+    let notes = headers.len() - 1;
+
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(&headers).map_err(|e| anyhow!(e.to_string()))?;
+
+    for calculation in calculations {
+        let mut record: Vec<String> = calculation_values(calculation).map(str::to_string).to_vec();
+        record.extend(derived_values(calculation, derived));
+        record.push(describe_samples_omitted(&calculation.samples_omitted));
+        if with_condition {
+            record.insert(2, calculation.condition.clone().unwrap_or_default());
+        }
+
+        wtr.write_record(&record)?;
+    }
+
+    // Peptides whose dataset failed keep a row so they don't silently disappear from the results
+    for failure in failures {
+        for (protein, peptide) in &failure.peptides {
+            let mut record = vec![String::new(); headers.len()];
+            record[0] = protein.clone();
+            record[1] = peptide.clone();
+            if with_condition {
+                record[2] = failure.condition.clone().unwrap_or_default();
+            }
+            record[notes] = format!("Failed: {}", failure.error);
+
+            wtr.write_record(&record)?;
+        }
+    }
+
+    wtr.flush()?;
+
+
+    Ok(())
+}
+
+fn protein_headers(with_condition: bool) -> Vec<&'static str> {
+    let mut headers = vec![
+        "Protein",
+        "Peptides",
+        "Median",
+        "Weighted mean",
+        "Q1",
+        "Q3",
+        "IQR",
+        "Outliers excluded",
+        "Not a number",
+    ];
+    if with_condition {
+        headers.insert(1, "Condition");
+    }
+    headers
+}
+
+fn protein_values(summary: &ProteinSummary, with_condition: bool) -> Vec<String> {
+    let number = |value: f64| if value.is_finite() { value.to_string() } else { String::new() };
+
+    let mut values = vec![
+        summary.protein.clone(),
+        summary.peptides.to_string(),
+        number(summary.median),
+        number(summary.weighted_mean),
+        number(summary.first_quartile),
+        number(summary.third_quartile),
+        number(summary.interquartile_range()),
+        summary.outliers.to_string(),
+        summary.missing.to_string(),
+    ];
+    if with_condition {
+        values.insert(1, summary.condition.clone().unwrap_or_default());
+    }
+    values
+}
+
+/// Writes the protein level roll-up of the rate constants as CSV.
+pub fn serialize_protein_summaries(path: &Path, summaries: &[ProteinSummary]) -> anyhow::Result<()> {
+    let with_condition = summaries.iter().any(|summary| summary.condition.is_some());
+
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(protein_headers(with_condition))?;
+
+    for summary in summaries {
+        wtr.write_record(protein_values(summary, with_condition))?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
+
+const COMPARISON_HEADERS: [&str; 15] = [
+    "Level",
+    "Protein",
+    "Peptide",
+    "Condition A",
+    "Condition B",
+    "Mean A",
+    "Mean B",
+    "n A",
+    "n B",
+    "Difference",
+    "Fold change",
+    "log2 fold change",
+    "t",
+    "p-value",
+    "q-value",
+];
+
+fn comparison_values(comparison: &Comparison) -> [String; 15] {
+    // Statistics that couldn't be computed are left empty
+    let number = |value: f64| if value.is_finite() { value.to_string() } else { String::new() };
+
+    [
+        comparison.level.name().to_string(),
+        comparison.protein.clone(),
+        comparison.peptide.clone(),
+        comparison.condition_a.clone(),
+        comparison.condition_b.clone(),
+        number(comparison.mean_a),
+        number(comparison.mean_b),
+        comparison.n_a.to_string(),
+        comparison.n_b.to_string(),
+        number(comparison.difference),
+        number(comparison.fold_change),
+        number(comparison.log2_fold_change()),
+        number(comparison.statistic),
+        number(comparison.p_value),
+        number(comparison.q_value),
+    ]
+}
+
+/// Writes the comparisons between conditions as CSV.
+pub fn serialize_comparisons(path: &Path, comparisons: &[Comparison]) -> anyhow::Result<()> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(COMPARISON_HEADERS)?;
+
+    for comparison in comparisons {
+        wtr.write_record(comparison_values(comparison))?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
+
+/// Writes the rate constants as an Excel workbook with four sheets: the results with numeric
+/// cells and the `derived` columns, the protein roll-up, the `parameters` the run used and the
+/// samples omitted for each peptide. Another sheet holds the `comparisons` between conditions,
+/// when there are any.
+#[allow(clippy::too_many_arguments)]
+pub fn serialize_workbook(
+    path: &Path,
+    calculations: &[Calculation],
+    failures: &[DatasetFailure],
+    derived: &[DerivedMetric],
+    proteins: &[ProteinSummary],
+    comparisons: &[Comparison],
+    parameters: &[(&str, String)],
+) -> anyhow::Result<()> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold();
+
+    let with_condition = has_conditions(calculations, failures);
+    let mut headers = calculation_headers(with_condition, derived);
+    let notes = headers.len() as u16 - 1;
+    headers[notes as usize] = "Notes";
+    // Columns after the peptide name move right by one when there's a Condition column
+    let offset = if with_condition { 1 } else { 0 };
+
+    let results = workbook.add_worksheet().set_name("Rate Constants")?;
+    write_header(results, &headers, &header)?;
+
+    let mut row = 1;
+    for calculation in calculations {
+        let values = calculation_values(calculation);
+
+        results.write_string(row, 0, values[0])?;
+        results.write_string(row, 1, values[1])?;
+        if let Some(condition) = &calculation.condition {
+            results.write_string(row, 2, condition)?;
+        }
+        for (col, value) in values.iter().enumerate().skip(2) {
+            write_value(results, row, col as u16 + offset, value)?;
+        }
+        for (col, value) in derived_values(calculation, derived).iter().enumerate() {
+            if !value.is_empty() {
+                write_value(results, row, 12 + offset + col as u16, value)?;
+            }
+        }
+        results.write_string(row, notes, describe_samples_omitted(&calculation.samples_omitted))?;
+        row += 1;
+    }
+
+    for failure in failures {
+        for (protein, peptide) in &failure.peptides {
+            results.write_string(row, 0, protein)?;
+            results.write_string(row, 1, peptide)?;
+            if let Some(condition) = &failure.condition {
+                results.write_string(row, 2, condition)?;
+            }
+            results.write_string(row, notes, format!("Failed: {}", failure.error))?;
+            row += 1;
+        }
+    }
+
+    results.set_freeze_panes(1, 0)?;
+    results.autofit();
+
+    let with_condition = proteins.iter().any(|summary| summary.condition.is_some());
+    let text_columns = if with_condition { 2 } else { 1 };
+    let sheet = workbook.add_worksheet().set_name("Proteins")?;
+    write_header(sheet, &protein_headers(with_condition), &header)?;
+    for (row, summary) in proteins.iter().enumerate() {
+        let row = row as u32 + 1;
+        for (col, value) in protein_values(summary, with_condition).iter().enumerate() {
+            if col < text_columns {
+                sheet.write_string(row, col as u16, value)?;
+            } else if !value.is_empty() {
+                write_value(sheet, row, col as u16, value)?;
+            }
+        }
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Parameters")?;
+    write_header(sheet, &["Parameter", "Value"], &header)?;
+    for (row, (name, value)) in parameters.iter().enumerate() {
+        sheet.write_string(row as u32 + 1, 0, *name)?;
+        write_value(sheet, row as u32 + 1, 1, value)?;
+    }
+    sheet.autofit();
+
+    // One row per omitted sample, with a column for every metadata row of the input
+    let mut attributes: Vec<&str> = vec![];
+    for sample in calculations.iter().flat_map(|calculation| &calculation.samples_omitted) {
+        for (name, _) in &sample.attributes {
+            if !attributes.contains(&name.as_str()) {
+                attributes.push(name);
+            }
+        }
+    }
+
+    let sheet = workbook.add_worksheet().set_name("Omitted Samples")?;
+    let mut headers = vec!["Protein", "Peptide", "Sample", "Day", "Mouse", "Body water enrichment"];
+    if has_conditions(calculations, failures) {
+        headers.insert(2, "Condition");
+    }
+    headers.extend(&attributes);
+    write_header(sheet, &headers, &header)?;
+    let omitted = calculations.iter()
+        .flat_map(|calculation| calculation.samples_omitted.iter().map(move |sample| (calculation, sample)));
+    for (row, (calculation, sample)) in omitted.enumerate() {
+        let row = row as u32 + 1;
+        sheet.write_string(row, 0, &calculation.protein)?;
+        sheet.write_string(row, 1, &calculation.peptide)?;
+        if let Some(condition) = &calculation.condition {
+            sheet.write_string(row, 2, condition)?;
+        }
+        sheet.write_string(row, 2 + offset, &sample.name)?;
+        sheet.write_number(row, 3 + offset, sample.day as f64)?;
+        write_value(sheet, row, 4 + offset, &sample.mouse)?;
+        write_value(sheet, row, 5 + offset, &sample.label)?;
+        for (col, attribute) in attributes.iter().enumerate() {
+            write_value(sheet, row, 6 + offset + col as u16, sample.attribute(attribute).unwrap_or_default())?;
+        }
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+
+    if !comparisons.is_empty() {
+        let sheet = workbook.add_worksheet().set_name("Comparison")?;
+        write_header(sheet, &COMPARISON_HEADERS, &header)?;
+        for (row, comparison) in comparisons.iter().enumerate() {
+            let row = row as u32 + 1;
+            for (col, value) in comparison_values(comparison).iter().enumerate() {
+                match col {
+                    0..=4 => {
+                        sheet.write_string(row, col as u16, value)?;
+                    }
+                    _ if value.is_empty() => {}
+                    _ => write_value(sheet, row, col as u16, value)?,
+                }
+            }
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        sheet.autofit();
+    }
+
+    workbook.save(path)?;
+
+    Ok(())
+}
+
+fn write_header(sheet: &mut Worksheet, headers: &[&str], format: &Format) -> anyhow::Result<()> {
+    for (col, header) in headers.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *header, format)?;
+    }
+
+    Ok(())
+}
+
+// Numbers are written as numeric cells, anything else (such as "-nan(ind)") as text
+fn write_value(sheet: &mut Worksheet, row: u32, col: u16, value: &str) -> anyhow::Result<()> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => sheet.write_number(row, col, number)?,
+        _ => sheet.write_string(row, col, value)?,
+    };
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calculation(mean: &str, two_sd: (&str, &str)) -> Calculation {
+        let fields = ["Bsa", "LVTDLTK", "4.59", "2", mean, "8", "0.5", "0.6", two_sd.0, "6", two_sd.1, "6"];
+        Calculation::from_fields(&fields, vec![], None)
+    }
+
+    #[test]
+    fn writes_derived_values() {
+        let derived = [DerivedMetric::HalfLife, DerivedMetric::Turnover];
+        assert_eq!(
+            derived_values(&calculation("0.1", ("0.05", "0.2")), &derived),
+            ["6.931472", "3.465736", "13.862944", "10", "5", "20"],
+        );
+    }
+
+    #[test]
+    fn leaves_derived_values_that_cant_be_computed_empty() {
+        let derived = [DerivedMetric::HalfLife, DerivedMetric::Turnover];
+
+        // No half-life for a lower bound at or below zero
+        assert_eq!(
+            derived_values(&calculation("0.1", ("-0.02", "0.22")), &derived),
+            ["6.931472", "3.150669", "", "10", "-2", "22"],
+        );
+        assert_eq!(
+            derived_values(&calculation("-nan(ind)", ("-nan(ind)", "-nan(ind)")), &derived),
+            ["", "", "", "", "", ""],
+        );
+    }
+
+    #[test]
+    fn writes_the_engine_text_back() {
+        let calculation = calculation("-nan(ind)", ("0.000400", "0.001"));
+        assert_eq!(calculation_values(&calculation)[4..], ["-nan(ind)", "8", "0.5", "0.6", "0.000400", "6", "0.001", "6"]);
+    }
+}
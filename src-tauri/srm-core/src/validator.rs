@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Serialize;
+use crate::error::PipelineError;
+use crate::parser::{DAY_LABEL, Day, ENRICHMENT_LABEL, Layout, MOUSE_LABEL, PROTEIN_LABEL, read_rows, Sheet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The file can't be processed until this is fixed.
+    Error,
+    /// The file can be processed but part of the data is ignored.
+    Warning,
+}
+
+/// Something wrong with a cell or a row, with 1-based row and column numbers.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputProblem {
+    pub row: usize,
+    pub column: Option<usize>,
+    pub value: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl InputProblem {
+    fn cell(row: usize, column: usize, value: &str, severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            row,
+            column: Some(column + 1),
+            value: value.to_string(),
+            severity,
+            message: message.into(),
+        }
+    }
+
+    fn row(row: usize, severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            row,
+            column: None,
+            value: String::new(),
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+/// Reads the input like [`crate::parser::parse`] but keeps going after a problem, so every
+/// problem in the file is reported at once. An empty list means the file is ready to process.
+///
+/// Fails only when the file itself can't be read.
+pub async fn validate(spreadsheet: &Path, sheet: Option<&Sheet>) -> Result<Vec<InputProblem>, PipelineError> {
+    let rows = read_rows(spreadsheet, sheet).await?;
+    let mut rows = rows.into_iter();
+    let mut problems = vec![];
+
+    let layout = match Layout::locate(spreadsheet, &mut rows) {
+        Ok(layout) => layout,
+        Err(err) => {
+            let row = match &err {
+                PipelineError::Parse { row: Some(row), .. } => *row,
+                _ => 1,
+            };
+            problems.push(InputProblem::row(row, Severity::Error, err.to_string()));
+            return Ok(problems);
+        }
+    };
+
+    for label in [DAY_LABEL, MOUSE_LABEL, ENRICHMENT_LABEL] {
+        if layout.header_row(label).is_none() {
+            problems.push(InputProblem::row(
+                layout.protein_row,
+                Severity::Error,
+                format!("Couldn't find the {label} row above the {PROTEIN_LABEL} header row"),
+            ));
+        }
+    }
+
+    if let Some((line, days)) = layout.header_row(DAY_LABEL) {
+        if layout.samples.is_empty() {
+            problems.push(InputProblem::row(*line, Severity::Error, "Day row doesn't have any days"));
+        }
+
+        for &column in &layout.samples {
+            let value = days.get(column).map_or("", |day| day.trim());
+            if value.parse::<Day>().is_err() {
+                problems.push(InputProblem::cell(*line, column, value, Severity::Error, "Day isn't a whole number"));
+            }
+        }
+    }
+
+    let last_sample = layout.samples.last().copied();
+    let sample_count = layout.samples.len();
+    for label in [MOUSE_LABEL, ENRICHMENT_LABEL] {
+        if let Some((line, record)) = layout.header_row(label) {
+            let count = layout.samples.iter()
+                .filter(|&&column| record.get(column).is_some_and(|value| !value.trim().is_empty()))
+                .count();
+            if count != sample_count || last_sample.is_some_and(|last| has_values_after(&layout, record, last)) {
+                problems.push(InputProblem::row(
+                    *line,
+                    Severity::Error,
+                    format!("{label} row has {count} value(s) for the {sample_count} samples in the Day row"),
+                ));
+            }
+        }
+    }
+
+    let mut seen: HashMap<(String, String, String), usize> = HashMap::new();
+
+    for (line, record) in rows {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            problems.push(InputProblem::row(line, Severity::Warning, "Empty row between peptides, it will be skipped"));
+            continue;
+        }
+
+        let field = |column: usize| record.get(column).map_or("", |value| value.trim());
+        let (protein, peptide, ratio) = (layout.protein, layout.peptide, layout.mass_charge_ratio);
+
+        if field(protein).is_empty() {
+            problems.push(InputProblem::cell(line, protein, "", Severity::Warning, "Protein name is empty"));
+        }
+        if field(peptide).is_empty() {
+            problems.push(InputProblem::cell(line, peptide, "", Severity::Warning, "Peptide name is empty"));
+        }
+        if field(ratio).parse::<f64>().map_or(true, |ratio| !ratio.is_finite()) {
+            problems.push(InputProblem::cell(line, ratio, field(ratio), Severity::Warning, "Product m/z isn't a number and the row will be skipped"));
+        }
+
+        if let Some(charge) = layout.charge {
+            if !field(charge).is_empty() && layout.charge_of(&record).is_none() {
+                problems.push(InputProblem::cell(
+                    line,
+                    charge,
+                    field(charge),
+                    Severity::Warning,
+                    "Charge isn't a whole number, the peptide will be grouped without it",
+                ));
+            }
+        }
+
+        if last_sample.is_some_and(|last| has_values_after(&layout, &record, last)) {
+            problems.push(InputProblem::row(
+                line,
+                Severity::Warning,
+                format!("Row has values past the {sample_count} samples in the Day row, they will be ignored"),
+            ));
+        }
+
+        for &column in &layout.samples {
+            let value = field(column);
+            if value.is_empty() || value == "#N/A" {
+                continue;
+            }
+
+            if value.parse::<f64>().map_or(true, |intensity| !intensity.is_finite()) {
+                problems.push(InputProblem::cell(
+                    line,
+                    column,
+                    value,
+                    Severity::Warning,
+                    "Intensity isn't a number and will be treated as missing",
+                ));
+            }
+        }
+
+        let key = (field(protein).to_string(), field(peptide).to_string(), field(ratio).to_string());
+        if let Some(first) = seen.get(&key) {
+            problems.push(InputProblem::row(
+                line,
+                Severity::Warning,
+                format!("Same protein, peptide and m/z as row {first}"),
+            ));
+        } else {
+            seen.insert(key, line);
+        }
+    }
+
+    Ok(problems)
+}
+
+fn has_values_after(layout: &Layout, record: &[String], column: usize) -> bool {
+    record.iter().enumerate()
+        .skip(column + 1)
+        .any(|(column, value)| !layout.is_peptide_column(column) && !value.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "\
+Day,,,0,14,
+Mouse,,,Unlabeled,1,
+Body water enrichment,,,Unlabeled,3.994,
+Protein,Peptide,Product Mz,Unlabeled_1,1,
+";
+
+    // Row, column, severity and message of every problem
+    async fn problems(contents: &str) -> Vec<(usize, Option<usize>, Severity, String)> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.csv");
+        tokio::fs::write(&path, contents).await.unwrap();
+
+        validate(&path, None).await.unwrap()
+            .into_iter()
+            .map(|problem| (problem.row, problem.column, problem.severity, problem.message))
+            .collect()
+    }
+
+    async fn peptide_problems(rows: &str) -> Vec<(usize, Option<usize>, Severity, String)> {
+        problems(&(HEADER.to_string() + rows)).await
+    }
+
+    fn problem(row: usize, column: Option<usize>, severity: Severity, message: &str) -> (usize, Option<usize>, Severity, String) {
+        (row, column, severity, message.to_string())
+    }
+
+    #[tokio::test]
+    async fn accepts_a_valid_file() {
+        assert!(peptide_problems("Bsa,LVTDLTK,395.239461,1,#N/A,\n").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_a_missing_protein_row() {
+        assert_eq!(problems("Day,,,0,14\nMouse,,,Unlabeled,1\n").await, [
+            problem(1, None, Severity::Error, "Couldn't find the Protein header row"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_missing_header_rows() {
+        let contents = "Day,,,0,14\nProtein,Peptide,Product Mz,A,B\nBsa,LVTDLTK,395.2,1,2\n";
+        assert_eq!(problems(contents).await, [
+            problem(2, None, Severity::Error, "Couldn't find the Mouse row above the Protein header row"),
+            problem(2, None, Severity::Error, "Couldn't find the Body water enrichment row above the Protein header row"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_day_problems() {
+        let without_days = HEADER.replace("Day,,,0,14,", "Day,,,,,");
+        assert_eq!(problems(&without_days).await, [
+            problem(1, None, Severity::Error, "Day row doesn't have any days"),
+        ]);
+
+        let fractional_day = HEADER.replace("Day,,,0,14,", "Day,,,0,1.5,");
+        assert_eq!(problems(&fractional_day).await, [
+            problem(1, Some(5), Severity::Error, "Day isn't a whole number"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_header_rows_that_dont_match_the_days() {
+        let missing_mouse = HEADER.replace("Mouse,,,Unlabeled,1,", "Mouse,,,Unlabeled,,");
+        assert_eq!(problems(&missing_mouse).await, [
+            problem(2, None, Severity::Error, "Mouse row has 1 value(s) for the 2 samples in the Day row"),
+        ]);
+
+        let extra_enrichment = HEADER.replace("Unlabeled,3.994,", "Unlabeled,3.994,4.1");
+        assert_eq!(problems(&extra_enrichment).await, [
+            problem(3, None, Severity::Error, "Body water enrichment row has 2 value(s) for the 2 samples in the Day row"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_empty_rows_as_warnings() {
+        assert_eq!(peptide_problems("Bsa,LVTDLTK,395.239461,1,2,\n,,,,,\n").await, [
+            problem(6, None, Severity::Warning, "Empty row between peptides, it will be skipped"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_empty_names() {
+        assert_eq!(peptide_problems(",,395.239461,1,2,\n").await, [
+            problem(5, Some(1), Severity::Warning, "Protein name is empty"),
+            problem(5, Some(2), Severity::Warning, "Peptide name is empty"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_unreadable_mass_charge_ratios() {
+        assert_eq!(peptide_problems("Bsa,LVTDLTK,NaN,1,2,\n").await, [
+            problem(5, Some(3), Severity::Warning, "Product m/z isn't a number and the row will be skipped"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_unreadable_charges() {
+        let contents = "\
+Day,,,,0,14
+Mouse,,,,Unlabeled,1
+Body water enrichment,,,,Unlabeled,3.994
+Protein,Peptide,Product Mz,Charge,Unlabeled_1,1
+Bsa,LVTDLTK,395.239461,2+,1,2
+Bsa,YLYEIAR,464.7,two,1,2
+";
+        assert_eq!(problems(contents).await, [
+            problem(6, Some(4), Severity::Warning, "Charge isn't a whole number, the peptide will be grouped without it"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn accepts_a_charge_column_after_the_samples() {
+        let contents = "\
+Day,,,0,14,
+Mouse,,,Unlabeled,1,
+Body water enrichment,,,Unlabeled,3.994,
+Protein,Peptide,Product Mz,Unlabeled_1,1,Charge
+Bsa,LVTDLTK,395.239461,1,2,2
+";
+        assert!(problems(contents).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_intensity_problems() {
+        assert_eq!(peptide_problems("Bsa,LVTDLTK,395.239461,1,2,checked\n").await, [
+            problem(5, None, Severity::Warning, "Row has values past the 2 samples in the Day row, they will be ignored"),
+        ]);
+        assert_eq!(peptide_problems("Bsa,LVTDLTK,395.239461,lots,2,\n").await, [
+            problem(5, Some(4), Severity::Warning, "Intensity isn't a number and will be treated as missing"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_duplicate_rows() {
+        let rows = "Bsa,LVTDLTK,395.239461,1,2,\nBsa,YLYEIAR,464.7,1,2,\nBsa,LVTDLTK,395.239461,3,4,\n";
+        assert_eq!(peptide_problems(rows).await, [
+            problem(7, None, Severity::Warning, "Same protein, peptide and m/z as row 5"),
+        ]);
+    }
+}
@@ -0,0 +1,63 @@
+//! Compares the native engine with the bundled SRM_Rate.exe on the example input.
+
+use std::path::{Path, PathBuf};
+
+use srm_core::engine::builtin_engine;
+use srm_core::{process_file, DynEngine, EngineType, InputFile, ProcessOptions, Runner, WorkerPool};
+
+fn example_input() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples/Input Data.csv")
+}
+
+// Rate constants by protein and peptide, `None` where the engine couldn't compute one
+async fn rate_constants(engine: &dyn DynEngine, dir: &Path) -> Vec<(String, String, Option<f64>)> {
+    let input = dir.join("Input Data.csv");
+    std::fs::copy(example_input(), &input).unwrap();
+
+    let summary = process_file(
+        engine,
+        &WorkerPool::new(2),
+        &ProcessOptions::default(),
+        InputFile { uuid: String::new(), path: input, sheet: None },
+        Box::new(|_| {}),
+    ).await.unwrap();
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(&summary.output).unwrap();
+    reader.records()
+        .map(|record| {
+            let record = record.unwrap();
+            (record[0].to_string(), record[1].to_string(), record[4].parse::<f64>().ok().filter(|rate| rate.is_finite()))
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore = "runs SRM_Rate.exe, which needs wine outside of Windows"]
+async fn native_engine_matches_srm_rate() {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets");
+    let srm_rate = builtin_engine(EngineType::Single, &assets, Runner::for_platform());
+    let native = builtin_engine(EngineType::Native, &assets, Runner::direct());
+
+    let (srm_rate_dir, native_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    let expected = rate_constants(srm_rate.as_ref(), srm_rate_dir.path()).await;
+    let actual = rate_constants(native.as_ref(), native_dir.path()).await;
+
+    assert_eq!(expected.len(), actual.len());
+    let mut compared = 0;
+    for (expected, actual) in expected.iter().zip(&actual) {
+        assert_eq!((&expected.0, &expected.1), (&actual.0, &actual.1));
+
+        if let (Some(expected_rate), Some(actual_rate)) = (expected.2, actual.2) {
+            let difference = (expected_rate - actual_rate).abs();
+            assert!(
+                difference <= 0.1 * expected_rate.abs() + 5e-4,
+                "{} {}: SRM_Rate.exe gave {expected_rate}, the native engine {actual_rate}",
+                expected.0, expected.1,
+            );
+            compared += 1;
+        }
+    }
+
+    assert!(compared > expected.len() / 2, "only {compared} rate constants were computed by both engines");
+}
@@ -0,0 +1,42 @@
+export type PipelineError =
+    | { kind: 'parse', path: string, row: number | null, column: number | null, message: string }
+    | { kind: 'grouping', path: string, message: string }
+    | { kind: 'serialization', path: string, message: string }
+    | { kind: 'engine_launch', program: string, message: string }
+    | { kind: 'engine_failure', program: string, status: number | null, message: string, stderr: string }
+    | { kind: 'engine_timeout', program: string, seconds: number }
+    | { kind: 'aggregation', path: string, message: string }
+    | { kind: 'invalid_option', name: string, message: string }
+
+export function describeError(error: PipelineError): string {
+    switch (error.kind) {
+        case 'parse': {
+            const location = error.row === null ? '' : error.column === null
+                ? ` (row ${error.row})`
+                : ` (row ${error.row}, column ${error.column})`
+            return `${error.message}${location}. Check the spreadsheet layout or validate the file for a full list of problems.`
+        }
+        case 'grouping':
+            return `${error.message}. Make sure the file lists peptides below the Protein header row.`
+        case 'serialization':
+            return `${error.message}. Check that ${error.path} is writable and the disk isn't full.`
+        case 'engine_launch':
+            return `${error.message}. Check that ${error.program} is installed and can be started.`
+        case 'engine_failure':
+            return error.stderr === '' ? error.message : `${error.message}: ${error.stderr}`
+        case 'engine_timeout':
+            return `The engine didn't finish within ${error.seconds} seconds. Try a longer timeout or the native engine.`
+        case 'aggregation':
+            return `${error.message}. The engine may have written its results in an unexpected format.`
+        case 'invalid_option':
+            return `${error.message}. Change the ${error.name} in the settings.`
+    }
+}
+
+// Commands reject with a PipelineError, or a plain string for errors outside the pipeline
+export function describeRejection(error: unknown): string {
+    if (typeof error === 'object' && error !== null && 'kind' in error) {
+        return describeError(error as PipelineError)
+    }
+    return String(error)
+}
@@ -2,9 +2,11 @@ import { z } from 'zod'
 import type { SuperForm } from 'sveltekit-superforms'
 import type { PathObject } from 'path-browserify'
 
-export const engineSchema = z.enum(['single', 'multi'])
+export const engineSchema = z.enum(['single', 'multi', 'native'])
 export type engineType = z.infer<typeof engineSchema>
 
+export const groupingSchema = z.enum(['heuristic', 'charge', 'ppm'])
+
 export const PathSchema: z.ZodType<PathObject> = z.any()
 export const inputFileSchema = z.object({
     uuid: z.string(),
@@ -20,7 +22,42 @@ export const schema = z.object({
     engineType: engineSchema,
     shouldRemoveNACalculations: z.boolean(),
     toleranceMultiplier: z.coerce.number().min(0),
+    // Seconds, 0 lets the engine run for as long as it takes
+    engineTimeout: z.coerce.number().min(0),
+    // Engine runs at once across all files, 0 uses one per CPU
+    workers: z.coerce.number().int().min(0),
+    groupingStrategy: groupingSchema,
+    ppmTolerance: z.coerce.number().positive(),
+    shouldUseCustomRunner: z.boolean(),
+    runnerWrapper: z.string(),
+    runnerEnv: z.string().refine(
+        env => env.split(/\s+/).filter(Boolean).every(variable => variable.indexOf('=') > 0),
+        'Use KEY=VALUE pairs separated by spaces',
+    ),
+    shouldWriteWorkbook: z.boolean(),
+    shouldSortByName: z.boolean(),
+    shouldAddDerivedColumns: z.boolean(),
+    shouldSplitByCondition: z.boolean(),
+    shouldCompareConditions: z.boolean(),
     inputFiles: z.array(inputFileSchema).min(0),
 })
 
-export type Form = SuperForm<z.infer<typeof schema>>
\ No newline at end of file
+export type Form = SuperForm<z.infer<typeof schema>>
+
+// Mirrors srm_core::Runner, null runs the engine through wine outside Windows
+export function runner(data: z.infer<typeof schema>) {
+    if (!data.shouldUseCustomRunner) return null
+
+    const env = Object.fromEntries(data.runnerEnv.split(/\s+/).filter(Boolean).map(variable => {
+        const separator = variable.indexOf('=')
+        return [variable.slice(0, separator), variable.slice(separator + 1)]
+    }))
+    return { wrapper: data.runnerWrapper.split(/\s+/).filter(Boolean), env }
+}
+
+// Mirrors srm_core::GroupingStrategy
+export function groupingStrategy(data: z.infer<typeof schema>) {
+    return data.groupingStrategy === 'ppm'
+        ? { strategy: 'ppm', tolerance: data.ppmTolerance }
+        : { strategy: data.groupingStrategy }
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+// Mirrors srm_core::preview::GroupingPreview, returned by the preview_grouping command
+
+export type SplitRule =
+    | { rule: 'threshold', threshold: number | null }
+    | { rule: 'isotope_spacing', charge: number, min: number, max: number }
+    | { rule: 'no_isotope_spacing' }
+    | { rule: 'charge_column', charge: number | null }
+
+export type SplitDecision = SplitRule & {
+    gap: number
+    joined: boolean
+}
+
+export interface TransitionPreview {
+    mass_charge_ratio: number
+    decision: SplitDecision | null
+}
+
+export interface PeptideGroupPreview {
+    protein: string
+    peptide: string
+    transitions: TransitionPreview[]
+    na_columns: boolean[]
+}
+
+export interface NAGroupPreview {
+    na_columns: boolean[]
+    peptide_groups: number[]
+}
+
+export interface GroupingPreview {
+    samples: string[]
+    peptide_groups: PeptideGroupPreview[]
+    na_groups: NAGroupPreview[]
+}
+
+// Why a transition joined or split from the previous one
+export function describeDecision(decision: SplitDecision): string {
+    const gap = decision.gap.toFixed(4)
+    const outcome = decision.joined ? 'joined' : 'split'
+
+    switch (decision.rule) {
+        case 'threshold':
+            return decision.threshold === null
+                ? `gap ${gap}, only one transition to compare with, ${outcome}`
+                : `gap ${gap} ${decision.joined ? '<' : '≥'} threshold ${decision.threshold.toFixed(4)}, ${outcome}`
+        case 'isotope_spacing':
+            return `gap ${gap} ${decision.joined ? 'within' : 'outside'} ${decision.min.toFixed(4)}–${decision.max.toFixed(4)} for charge ${decision.charge}, ${outcome}`
+        case 'no_isotope_spacing':
+            return `gap ${gap} isn't an isotope spacing, ${outcome}`
+        case 'charge_column':
+            return decision.charge === null
+                ? `no charge, ${outcome}`
+                : `charge ${decision.charge} ${decision.joined ? 'same as' : 'differs from'} the previous transition, ${outcome}`
+    }
+}
+
+// Sample names of the NA columns of a group
+export function describeNAColumns(preview: GroupingPreview, naColumns: boolean[]): string {
+    const names = preview.samples.filter((_, i) => naColumns[i])
+    return names.length === 0 ? 'none' : names.join(', ')
+}
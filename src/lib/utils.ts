@@ -62,9 +62,11 @@ export const flyAndScale = (
     }
 }
 
+const inputExtensions = ['.csv', '.xlsx', '.xlsm', '.xlsb', '.xls', '.ods']
+
 export function processInputFiles(paths: string[]): InputFile[] {
     return paths
-        .filter(filePath => path.extname(filePath) === '.csv')
+        .filter(filePath => inputExtensions.includes(path.extname(filePath).toLowerCase()))
         .map(filePath => ({
             uuid: uuidv4(),
             path: path.parse(filePath),